@@ -0,0 +1,209 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul};
+
+// Unsigned integer of arbitrary size, for results that can outgrow the primitive types.
+// Stored as base 2^32 limbs with the least significant limb first, and never with
+// trailing zero limbs so that each value has exactly one representation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BigUint {
+    limbs: Vec<u32>
+}
+
+impl BigUint {
+    pub fn zero() -> Self {
+        Self { limbs: vec!() }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    // None if the value doesn't fit into 128 bits
+    pub fn to_u128(&self) -> Option<u128> {
+        if self.limbs.len() > 4 {
+            return None;
+        }
+        Some(self.limbs.iter()
+            .rev()
+            .fold(0u128, |acc, &limb| (acc << 32) | limb as u128))
+    }
+
+    // None if the other value is larger, as the result can't be negative
+    pub fn checked_sub(&self, other: &Self) -> Option<Self> {
+        if other > self {
+            return None;
+        }
+        let mut limbs = Vec::<u32>::with_capacity(self.limbs.len());
+        let mut borrow = 0i64;
+        for (idx, &limb) in self.limbs.iter().enumerate() {
+            let difference = limb as i64 - *other.limbs.get(idx).unwrap_or(&0) as i64 - borrow;
+            borrow = if difference < 0 {1} else {0};
+            limbs.push((difference + (borrow << 32)) as u32);
+        }
+        Some(Self::from_limbs(limbs))
+    }
+
+    fn from_limbs(mut limbs: Vec<u32>) -> Self {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        Self { limbs }
+    }
+
+    // Divide by a small divisor, returning the quotient and remainder
+    fn div_rem_small(&self, divisor: u32) -> (Self, u32) {
+        let mut limbs = vec![0u32; self.limbs.len()];
+        let mut remainder = 0u64;
+        // Long division works from the most significant limb down
+        for (idx, &limb) in self.limbs.iter().enumerate().rev() {
+            let current = (remainder << 32) | limb as u64;
+            limbs[idx] = (current / divisor as u64) as u32;
+            remainder = current % divisor as u64;
+        }
+        (Self::from_limbs(limbs), remainder as u32)
+    }
+}
+
+impl From<u64> for BigUint {
+    fn from(value: u64) -> Self {
+        Self::from_limbs(vec!(value as u32, (value >> 32) as u32))
+    }
+}
+
+impl From<u128> for BigUint {
+    fn from(value: u128) -> Self {
+        Self::from_limbs((0..4).map(|limb| (value >> (limb * 32)) as u32).collect())
+    }
+}
+
+impl Add for &BigUint {
+    type Output = BigUint;
+
+    fn add(self, other: &BigUint) -> BigUint {
+        let length = self.limbs.len().max(other.limbs.len());
+        let mut limbs = Vec::<u32>::with_capacity(length + 1);
+        let mut carry = 0u64;
+        for idx in 0..length {
+            let sum = *self.limbs.get(idx).unwrap_or(&0) as u64
+                + *other.limbs.get(idx).unwrap_or(&0) as u64
+                + carry;
+            limbs.push(sum as u32);
+            carry = sum >> 32;
+        }
+        limbs.push(carry as u32);
+        BigUint::from_limbs(limbs)
+    }
+}
+
+impl Mul for &BigUint {
+    type Output = BigUint;
+
+    fn mul(self, other: &BigUint) -> BigUint {
+        // Schoolbook multiplication, with each limb product fitting comfortably in 64 bits
+        let mut limbs = vec![0u32; self.limbs.len() + other.limbs.len()];
+        for (self_idx, &self_limb) in self.limbs.iter().enumerate() {
+            let mut carry = 0u64;
+            for (other_idx, &other_limb) in other.limbs.iter().enumerate() {
+                let current = limbs[self_idx + other_idx] as u64
+                    + self_limb as u64 * other_limb as u64
+                    + carry;
+                limbs[self_idx + other_idx] = current as u32;
+                carry = current >> 32;
+            }
+            limbs[self_idx + other.limbs.len()] = carry as u32;
+        }
+        BigUint::from_limbs(limbs)
+    }
+}
+
+impl Ord for BigUint {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Without trailing zero limbs, a longer value is always the larger one
+        self.limbs.len().cmp(&other.limbs.len())
+            .then_with(|| self.limbs.iter().rev().cmp(other.limbs.iter().rev()))
+    }
+}
+
+impl PartialOrd for BigUint {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+
+        // Peel off nine decimal digits at a time, least significant first
+        let mut chunks = Vec::<u32>::new();
+        let mut remaining = self.clone();
+        while !remaining.is_zero() {
+            let (quotient, chunk) = remaining.div_rem_small(1_000_000_000);
+            chunks.push(chunk);
+            remaining = quotient;
+        }
+
+        write!(f, "{}", chunks.last().unwrap())?;
+        for chunk in chunks.iter().rev().skip(1) {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::Rng;
+
+    // Values of any width up to the maximum, including zero, so that operands often differ in
+    // their number of limbs
+    fn random_u128(rng: &mut Rng, max_bits: u32) -> u128 {
+        let value = ((rng.next_u64() as u128) << 64) | rng.next_u64() as u128;
+        // Shifting out all 128 bits leaves zero
+        value.checked_shr(128 - max_bits + rng.below(max_bits as u64 + 1) as u32).unwrap_or(0)
+    }
+
+    #[test]
+    fn arithmetic_matches_u128() {
+        let mut rng = Rng::new(128);
+        for _ in 0..10000 {
+            // Sums stay below 2^128, and products of values up to 64 bits stay below it too
+            let (first, second) = (random_u128(&mut rng, 127), random_u128(&mut rng, 127));
+            let (big_first, big_second) = (BigUint::from(first), BigUint::from(second));
+            assert_eq!((&big_first + &big_second).to_u128(), Some(first + second), "{} + {}", first, second);
+            assert_eq!(
+                big_first.checked_sub(&big_second).map(|difference| difference.to_u128().unwrap()),
+                first.checked_sub(second),
+                "{} - {}", first, second
+            );
+            assert_eq!(big_first.cmp(&big_second), first.cmp(&second), "{} against {}", first, second);
+            assert_eq!(big_first.to_string(), first.to_string());
+
+            let (first, second) = (random_u128(&mut rng, 64), random_u128(&mut rng, 64));
+            assert_eq!((&BigUint::from(first) * &BigUint::from(second)).to_u128(), Some(first * second), "{} * {}", first, second);
+        }
+    }
+
+    #[test]
+    fn values_wider_than_u128() {
+        let two_to_128 = &BigUint::from(u128::MAX) + &BigUint::from(1u64);
+        assert_eq!(two_to_128.to_string(), "340282366920938463463374607431768211456");
+        assert_eq!(two_to_128.to_u128(), None);
+        // Subtracting one has to borrow through every limb
+        assert_eq!(two_to_128.checked_sub(&BigUint::from(1u64)).unwrap().to_u128(), Some(u128::MAX));
+        assert!(two_to_128 > BigUint::from(u128::MAX));
+
+        // Every limb product carries into the next limb
+        let max_squared = &BigUint::from(u128::MAX) * &BigUint::from(u128::MAX);
+        assert_eq!(max_squared.to_string(), "115792089237316195423570985008687907852589419931798687112530834793049593217025");
+
+        let two_to_100 = BigUint::from(1u128 << 100);
+        let two_to_200 = &two_to_100 * &two_to_100;
+        assert_eq!(two_to_200.to_string(), "1606938044258990275541962092341162602522202993782792835301376");
+        assert_eq!(two_to_200.checked_sub(&two_to_200), Some(BigUint::zero()));
+    }
+}
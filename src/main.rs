@@ -2,6 +2,8 @@
 #![feature(drain_filter)] // Solution 22
 #![feature(result_into_ok_or_err)] // Solution 23
 
+mod bignum;
+mod random;
 mod utils;
 mod solutions;
 
@@ -0,0 +1,29 @@
+// Small xorshift pseudo-random generator for generating inputs to self-checks. Seeded explicitly
+// so that any failure found can be reproduced by running again with the same seed.
+pub struct Rng {
+    state: u64
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Xorshift can never leave an all-zero state, so that seed is nudged away from zero
+        Self { state: seed.max(1) }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    // Uniform-enough value from zero up to but excluding the bound
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    // Value between the two bounds, both inclusive
+    pub fn between(&mut self, low: i32, high: i32) -> i32 {
+        low + self.below((high - low) as u64 + 1) as i32
+    }
+}
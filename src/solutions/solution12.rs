@@ -1,6 +1,8 @@
-use crate::utils::read_string_pairs;
+use crate::utils::{flag_value, has_flag, read_string_pairs};
 
 use std::collections::{HashSet, HashMap};
+use std::fmt;
+use itertools::Itertools;
 
 struct Cave {
     name: String,
@@ -9,23 +11,279 @@ struct Cave {
 }
 type CaveMap = HashMap<String, Cave>;
 
+// The same caves numbered for quick lookup, with each small cave also given its own slot so that
+// the number of visits to every small cave can be packed into a single integer
+struct CaveGraph {
+    connections: Vec<Vec<usize>>,
+    // None for big caves, which are never recorded as visited
+    small_slots: Vec<Option<u32>>,
+    small_caves: u32,
+    start: usize,
+    end: usize
+}
+
+#[derive(Debug)]
+enum CaveSystemError {
+    // Paths need both ends to run between
+    MissingCave {name: &'static str},
+    // Big caves that are directly connected to each other, between which a path could go back
+    // and forth forever
+    EndlessPaths {connections: Vec<(String, String)>}
+}
+
+impl fmt::Display for CaveSystemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingCave {name} => write!(f, "there is no {} cave", name),
+            Self::EndlessPaths {connections} => {
+                let connections = connections.iter()
+                    .map(|(cave1, cave2)| format!("{}-{}", cave1, cave2))
+                    .collect::<Vec<String>>();
+                write!(f, "big caves can be revisited endlessly along {}", connections.join(", "))
+            }
+        }
+    }
+}
+
+// Too many small caves for their visits to be packed into a single integer under a budget
+#[derive(Debug)]
+struct TrackingError {
+    small_caves: u32,
+    budget: RevisitBudget
+}
+
+impl fmt::Display for TrackingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "can't track visits to {} small caves with {}", self.small_caves, self.budget)
+    }
+}
+
+// How many times a path may visit the same small cave. "start" can never be returned to either way.
+#[derive(Debug, Clone, Copy)]
+enum RevisitBudget {
+    // Up to this many revisits in total, spent on any small caves
+    Total(u32),
+    // Every small cave can be visited up to this many times
+    PerCave(u32)
+}
+
+// Paths counted so far, from a cave with the given small cave visits and revisits left
+type PathMemo = HashMap<(usize, u64, u32), u64>;
+
 pub fn solution12 () {
-    let cave_map = build_cave_map(&read_string_pairs("src/data/solution12.txt", '-'));
-    println!("{}", solution12a(&cave_map));
-    println!("{}", solution12b(&cave_map));
+    let cave_map = match build_cave_map(&read_string_pairs("src/data/solution12.txt", '-')) {
+        Ok(cave_map) => cave_map,
+        Err(error) => {
+            println!("Invalid cave system: {}", error);
+            return;
+        }
+    };
+    let cave_graph = CaveGraph::from_map(&cave_map);
+    for count in [solution12a(&cave_graph), solution12b(&cave_graph)] {
+        match count {
+            Ok(count) => println!("{}", count),
+            Err(error) => println!("Unable to count paths: {}", error)
+        }
+    }
+
+    if let Some(budget) = RevisitBudget::from_flags() {
+        match count_paths(&cave_graph, budget) {
+            Ok(count) => println!("{} paths with {}", count, budget),
+            Err(error) => println!("Unable to count paths: {}", error)
+        }
+    }
+
+    if has_flag("--paths") {
+        let through = flag_value("--through");
+        if let Some(name) = through.as_ref().filter(|name| !cave_map.contains_key(*name)) {
+            println!("There is no cave called {}", name);
+            return;
+        }
+        let limit = flag_value("--limit")
+            .map(|limit| limit.parse::<usize>().expect("Limit must be a non-negative number"));
+
+        // Listed paths follow the same revisit budget as counting, with none by default
+        let budget = RevisitBudget::from_flags().unwrap_or(RevisitBudget::Total(0));
+        let (paths, stopped_early) = list_paths(&cave_map, budget, through.as_deref(), limit);
+        for path in &paths {
+            println!("{}", path);
+        }
+        if stopped_early {
+            println!("Stopped after the first {} paths", paths.len());
+        } else {
+            println!("{} paths in total", paths.len());
+        }
+    }
+}
+
+fn solution12a(cave_graph: &CaveGraph) -> Result<u64, TrackingError> {
+    count_paths(cave_graph, RevisitBudget::Total(0))
+}
+
+fn solution12b(cave_graph: &CaveGraph) -> Result<u64, TrackingError> {
+    count_paths(cave_graph, RevisitBudget::Total(1))
+}
+
+impl RevisitBudget {
+    // A total number of revisits from --revisits, or a limit for each cave from --visit-limit
+    fn from_flags() -> Option<Self> {
+        let parse = |value: String| value.parse::<u32>().expect("Revisit budgets must be non-negative numbers");
+        if let Some(revisits) = flag_value("--revisits") {
+            Some(Self::Total(parse(revisits)))
+        } else {
+            flag_value("--visit-limit").map(|limit| {
+                let limit = parse(limit);
+                assert!(limit >= 1, "Each small cave must be allowed at least one visit");
+                Self::PerCave(limit)
+            })
+        }
+    }
+
+    fn initial_revisits(&self) -> u32 {
+        match self {
+            Self::Total(revisits) => *revisits,
+            // Each cave keeps track of its own visits instead
+            Self::PerCave(_) => 0
+        }
+    }
+
+    // The revisits left after going into a cave already visited the given number of times, or
+    // None if the budget doesn't allow it
+    fn enter_cave(&self, dest_cave: &Cave, visits: usize, revisits: u32) -> Option<u32> {
+        if dest_cave.revisitable || visits == 0 {
+            return Some(revisits);
+        }
+        if dest_cave.name == "start" {
+            return None;
+        }
+
+        match self {
+            Self::Total(_) if revisits > 0 => Some(revisits - 1),
+            Self::PerCave(limit) if visits < *limit as usize => Some(revisits),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for RevisitBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Total(revisits) => write!(f, "up to {} revisits in total", revisits),
+            Self::PerCave(limit) => write!(f, "up to {} visits to each small cave", limit)
+        }
+    }
+}
+
+impl CaveGraph {
+    fn from_map(cave_map: &CaveMap) -> Self {
+        // Number the caves in name order so that they always come out the same
+        let names = cave_map.keys().sorted().collect::<Vec<&String>>();
+        let ids = names.iter()
+            .enumerate()
+            .map(|(id, &name)| (name, id))
+            .collect::<HashMap<&String, usize>>();
+
+        let mut small_caves = 0;
+        let small_slots = names.iter()
+            .map(|&name| if cave_map[name].revisitable {
+                None
+            } else {
+                small_caves += 1;
+                Some(small_caves - 1)
+            })
+            .collect::<Vec<Option<u32>>>();
+
+        Self {
+            connections: names.iter()
+                .map(|&name| cave_map[name].connections.iter().map(|dest| ids[dest]).sorted().collect())
+                .collect(),
+            small_slots,
+            small_caves,
+            start: ids[&String::from("start")],
+            end: ids[&String::from("end")]
+        }
+    }
+}
+
+fn count_paths(cave_graph: &CaveGraph, budget: RevisitBudget) -> Result<u64, TrackingError> {
+    let mut counter = PathCounter::new(cave_graph, budget)?;
+    let start = cave_graph.start;
+    let (visits, revisits) = counter.enter(start, 0, budget.initial_revisits()).unwrap();
+    Ok(counter.count_from(start, visits, revisits))
 }
 
-fn solution12a(cave_map: &CaveMap) -> i32 {
-    let visited_caves = HashSet::<String>::new();
-    explore_cave(&cave_map["start"], cave_map, &visited_caves, false)
+// Counts paths to "end" under a revisit budget. The visits made to each small cave are packed
+// into slots of a single integer, each just wide enough for the most visits a cave can have.
+// Under a total budget it only matters whether a cave has been visited, as every visit after the
+// first costs the same, so each slot is a single bit.
+struct PathCounter<'a> {
+    cave_graph: &'a CaveGraph,
+    budget: RevisitBudget,
+    slot_width: u32,
+    memo: PathMemo
 }
 
-fn solution12b(cave_map: &CaveMap) -> i32 {
-    let visited_caves = HashSet::<String>::new();
-    explore_cave(&cave_map["start"], cave_map, &visited_caves, true)
+impl<'a> PathCounter<'a> {
+    fn new(cave_graph: &'a CaveGraph, budget: RevisitBudget) -> Result<Self, TrackingError> {
+        let slot_width = match budget {
+            RevisitBudget::Total(_) => 1,
+            RevisitBudget::PerCave(limit) => u32::BITS - limit.leading_zeros()
+        };
+        if cave_graph.small_caves as u64 * slot_width as u64 > u64::BITS as u64 {
+            return Err(TrackingError {small_caves: cave_graph.small_caves, budget});
+        }
+        Ok(Self {cave_graph, budget, slot_width, memo: PathMemo::new()})
+    }
+
+    // The small cave visits and revisits left after going into a cave, or None if the budget
+    // doesn't allow it
+    fn enter(&self, cave: usize, visits: u64, revisits: u32) -> Option<(u64, u32)> {
+        let slot = match self.cave_graph.small_slots[cave] {
+            Some(slot) => slot * self.slot_width,
+            // Big caves can always be visited
+            None => return Some((visits, revisits))
+        };
+        let cave_visits = (visits >> slot) & ((1 << self.slot_width) - 1);
+        if cave_visits == 0 {
+            return Some((visits | (1 << slot), revisits));
+        }
+        if cave == self.cave_graph.start {
+            return None;
+        }
+
+        match self.budget {
+            RevisitBudget::Total(_) if revisits > 0 => Some((visits, revisits - 1)),
+            RevisitBudget::PerCave(limit) if cave_visits < limit as u64 => Some((visits + (1 << slot), revisits)),
+            _ => None
+        }
+    }
+
+    // Count the paths to "end" from a cave, given the small cave visits on the way there. The
+    // count only depends on those and the revisits left, not on the order the caves were visited
+    // in, so it's remembered for any other path that arrives in the same state.
+    fn count_from(&mut self, cave: usize, visits: u64, revisits: u32) -> u64 {
+        if cave == self.cave_graph.end {
+            return 1;
+        }
+        if let Some(&count) = self.memo.get(&(cave, visits, revisits)) {
+            return count;
+        }
+
+        let cave_graph = self.cave_graph;
+        // The caves that can be entered are gathered up first, as counting from them needs the
+        // memo mutably
+        let count = cave_graph.connections[cave].iter()
+            .filter_map(|&dest| self.enter(dest, visits, revisits).map(|state| (dest, state)))
+            .collect::<Vec<(usize, (u64, u32))>>()
+            .into_iter()
+            .map(|(dest, (new_visits, new_revisits))| self.count_from(dest, new_visits, new_revisits))
+            .sum();
+        self.memo.insert((cave, visits, revisits), count);
+        count
+    }
 }
 
-fn build_cave_map(connections: &Vec<(String, String)>) -> CaveMap {
+fn build_cave_map(connections: &Vec<(String, String)>) -> Result<CaveMap, CaveSystemError> {
     let mut cave_map = CaveMap::new();
     for (cave1, cave2) in connections {
         // Connecting in both directions
@@ -43,40 +301,167 @@ fn build_cave_map(connections: &Vec<(String, String)>) -> CaveMap {
             cave_map_entry.connections.insert(dest_cave_name);
         }
     }
-    cave_map
-}
-
-fn explore_cave(cave: &Cave, cave_map: &CaveMap, visited: &HashSet<String>, can_revisit: bool) -> i32 {
-    // Need a new visited set for searching the child nodes as we can't add to the
-    // same reference set
-    let mut new_visited = visited.clone();
-    new_visited.insert(cave.name.clone());
-
-    match cave.name.as_str() {
-        // If we get to "end" we can terminate and return the success of this path
-        "end" => 1,
-        // Otherwise we examine all connections out of this cave
-        _ => cave.connections.iter()
-            .map(|dest| &cave_map[dest])
-            .filter_map(|dest_cave| {
-                let dest_cave_name = &dest_cave.name;
-                // Visiting a revisitable cave or any cave for the first time preserves
-                // our revisiting status
-                if dest_cave.revisitable || !visited.contains(dest_cave_name) {
-                    Some((dest_cave, can_revisit))
-                } else if dest_cave_name != "start" // If there is a non-start cave...
-                    && visited.contains(dest_cave_name) // ... that we had already visited ...
-                    && can_revisit { // ... but we still have our single revisit allowance
-                    // Then we can visit the cave by spending our revisit
-                    Some((dest_cave, false))
-                } else {
-                    // An un-revisitable cave that we have already visited when we have used
-                    // up our single revisit means that we cannot explore here. Returning None
-                    // will cause the filter_map operation to filter this branch out.
-                    None
-                }
-            })
-            .map(|(dest_cave, new_can_revisit)| explore_cave(dest_cave, cave_map, &new_visited, new_can_revisit))
-            .sum() // We want the sum of descendents that eventually get to "end" (return 1)
+
+    if let Some(name) = ["start", "end"].iter().copied().find(|name| !cave_map.contains_key(*name)) {
+        return Err(CaveSystemError::MissingCave {name});
+    }
+
+    // Big caves can always be entered again, so any cycle made only of them would give endless
+    // paths. Every cycle through big caves alone includes two big caves next to each other, so
+    // it's enough to check for those connections before any search can recurse forever.
+    let endless_connections = connections.iter()
+        .filter(|(cave1, cave2)| cave_map[cave1].revisitable && cave_map[cave2].revisitable)
+        .map(|(cave1, cave2)| (cave1.min(cave2).clone(), cave1.max(cave2).clone()))
+        .sorted()
+        .dedup()
+        .collect::<Vec<(String, String)>>();
+
+    if endless_connections.is_empty() {
+        Ok(cave_map)
+    } else {
+        Err(CaveSystemError::EndlessPaths {connections: endless_connections})
+    }
+}
+
+// Paths from "start" to "end" written as their caves separated by commas, e.g. start,A,b,A,end.
+// Optionally only the paths through a given cave are kept, and listing stops at a limit. Also
+// returns whether listing stopped early, which is only known once a path beyond the limit is found.
+fn list_paths(cave_map: &CaveMap, budget: RevisitBudget, through: Option<&str>, limit: Option<usize>) -> (Vec<String>, bool) {
+    let mut paths = Vec::<String>::new();
+    let mut stopped_early = false;
+
+    let walk = PathWalk {cave_map, budget};
+    walk.walk_paths(&cave_map["start"], &mut vec!(), budget.initial_revisits(), &mut |path| {
+        if through.iter().all(|name| path.contains(name)) {
+            if limit.iter().any(|&limit| paths.len() >= limit) {
+                stopped_early = true;
+                return false;
+            }
+            paths.push(path.join(","));
+        }
+        true
+    });
+    (paths, stopped_early)
+}
+
+// The caves and the revisit budget that paths are walked through
+struct PathWalk<'a> {
+    cave_map: &'a CaveMap,
+    budget: RevisitBudget
+}
+
+impl<'a> PathWalk<'a> {
+    // Walk every path from the cave to "end", handing each complete path to the visitor as the
+    // caves along it. Connections are taken in name order so paths always come out in the same
+    // order. The visitor returns false to stop the walk early, which is passed back up as the
+    // return value.
+    fn walk_paths(
+        &self,
+        cave: &'a Cave,
+        path: &mut Vec<&'a str>,
+        revisits: u32,
+        visitor: &mut impl FnMut(&[&str]) -> bool
+    ) -> bool {
+        path.push(&cave.name);
+        let keep_walking = match cave.name.as_str() {
+            "end" => visitor(path),
+            _ => cave.connections.iter()
+                .sorted()
+                .map(|dest| &self.cave_map[dest])
+                .all(|dest_cave| {
+                    let visits = path.iter().filter(|&&name| name == dest_cave.name).count();
+                    match self.budget.enter_cave(dest_cave, visits, revisits) {
+                        Some(new_revisits) => self.walk_paths(dest_cave, path, new_revisits, visitor),
+                        None => true
+                    }
+                })
+        };
+        path.pop();
+        keep_walking
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::Rng;
+
+    // The original search, which walks every path individually
+    fn explore_cave(cave: &Cave, cave_map: &CaveMap, visited: &HashSet<String>, budget: RevisitBudget, revisits: u32) -> i32 {
+        // Need a new visited set for searching the child nodes as we can't add to the
+        // same reference set
+        let mut new_visited = visited.clone();
+        new_visited.insert(cave.name.clone());
+
+        match cave.name.as_str() {
+            // If we get to "end" we can terminate and return the success of this path
+            "end" => 1,
+            // Otherwise we examine all connections out of this cave
+            _ => cave.connections.iter()
+                .map(|dest| &cave_map[dest])
+                .filter_map(|dest_cave| budget.enter_cave(dest_cave, visited.contains(&dest_cave.name) as usize, revisits)
+                    .map(|new_revisits| (dest_cave, new_revisits))
+                )
+                .map(|(dest_cave, new_revisits)| explore_cave(dest_cave, cave_map, &new_visited, budget, new_revisits))
+                .sum() // We want the sum of descendents that eventually get to "end" (return 1)
+        }
+    }
+
+    // Connections between a few small and big caves, always including "start" and "end" but never
+    // joining two big caves
+    fn random_connections(rng: &mut Rng) -> Vec<(String, String)> {
+        let names = ["start", "end", "a", "b", "c", "d", "A", "B"];
+        let mut connections = Vec::<(String, String)>::new();
+        while connections.len() < 10 {
+            // The first two connections always come from "start" and "end"
+            let cave1 = if connections.len() < 2 {names[connections.len()]} else {names[rng.below(8) as usize]};
+            let cave2 = names[rng.below(8) as usize];
+            let big = |name: &str| name == name.to_uppercase();
+            if cave1 != cave2 && !(big(cave1) && big(cave2)) {
+                connections.push((String::from(cave1), String::from(cave2)));
+            }
+        }
+        connections
+    }
+
+    // Answers must match the original search, with and without a revisit
+    #[test]
+    fn counts_match_exploration() {
+        let mut rng = Rng::new(12);
+        for _ in 0..300 {
+            let connections = random_connections(&mut rng);
+            let cave_map = build_cave_map(&connections).unwrap();
+            let cave_graph = CaveGraph::from_map(&cave_map);
+            for budget in [RevisitBudget::Total(0), RevisitBudget::Total(1)] {
+                let explored = explore_cave(&cave_map["start"], &cave_map, &HashSet::new(), budget, budget.initial_revisits()) as u64;
+                assert_eq!(count_paths(&cave_graph, budget).unwrap(), explored, "Counts differ with {} for {:?}", budget, connections);
+            }
+        }
     }
-}
\ No newline at end of file
+
+    // Listing walks paths under its own copy of the revisit rules, which must allow exactly the
+    // paths that are counted
+    #[test]
+    fn listed_paths_match_counts() {
+        let mut rng = Rng::new(49);
+        let budgets = [
+            RevisitBudget::Total(0), RevisitBudget::Total(1), RevisitBudget::Total(2), RevisitBudget::Total(3),
+            RevisitBudget::PerCave(1), RevisitBudget::PerCave(2), RevisitBudget::PerCave(3)
+        ];
+        for _ in 0..100 {
+            let connections = random_connections(&mut rng);
+            let cave_map = build_cave_map(&connections).unwrap();
+            let cave_graph = CaveGraph::from_map(&cave_map);
+            for &budget in &budgets {
+                let (paths, _) = list_paths(&cave_map, budget, None, None);
+                assert_eq!(paths.len() as u64, count_paths(&cave_graph, budget).unwrap(), "Counts differ with {} for {:?}", budget, connections);
+            }
+        }
+    }
+
+    #[test]
+    fn missing_end_is_reported() {
+        let connections = vec!((String::from("start"), String::from("A")), (String::from("A"), String::from("b")));
+        assert!(matches!(build_cave_map(&connections), Err(CaveSystemError::MissingCave {name: "end"})));
+    }
+}
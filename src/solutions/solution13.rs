@@ -1,12 +1,25 @@
-use crate::utils::read_string_lines;
+use crate::utils::{flag_value, has_flag, read_string_lines};
 
 use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
 
 pub fn solution13 () {
     let (dots, instructions) = parse_input(read_string_lines("src/data/solution13.txt"));
 
     println!("{}", solution13a(&dots, &instructions));
     println!("{}", solution13b(&dots, &instructions));
+
+    if has_flag("--folds") {
+        print_fold_stages(&dots, &instructions);
+    }
+    if let Some(prefix) = flag_value("--fold-images") {
+        match write_fold_stages(&dots, &instructions, &prefix) {
+            Ok(count) => println!("Wrote {} images to {}*.pbm", count, prefix),
+            Err(error) => println!("Unable to write images to {}: {}", prefix, error)
+        }
+    }
 }
 
 type Dot = (i32, i32);
@@ -16,36 +29,95 @@ struct Fold {
     coordinate: i32
 }
 
+// Size of the paper, which can extend past the furthest dots
+#[derive(Clone, Copy)]
+struct Paper {
+    width: i32,
+    height: i32
+}
+
+impl Paper {
+    // The smallest paper that holds all the dots
+    fn around(dots: &HashSet<Dot>) -> Self {
+        Self {
+            width: dots.iter().map(|(x, _y)| x + 1).max().unwrap_or(0),
+            height: dots.iter().map(|(_x, y)| y + 1).max().unwrap_or(0)
+        }
+    }
+}
+
+impl Fold {
+    // How far the part folded over reaches past the start of the paper, when it's bigger than the
+    // part kept. The paper must reach at least past the fold line.
+    fn overhang(&self, paper: &Paper) -> i32 {
+        let size = match self.axis {
+            ParseAxis::X => paper.width,
+            ParseAxis::Y => paper.height
+        }.max(self.coordinate + 1);
+        (size - 1 - 2 * self.coordinate).max(0)
+    }
+
+    fn folded_paper(&self, paper: &Paper) -> Paper {
+        // Whichever part of the paper is bigger sets the size of the folded paper
+        let folded_size = self.coordinate + self.overhang(paper);
+        match self.axis {
+            ParseAxis::X => Paper {width: folded_size, height: paper.height},
+            ParseAxis::Y => Paper {width: paper.width, height: folded_size}
+        }
+    }
+}
+
+impl fmt::Display for Fold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.axis {
+            ParseAxis::X => write!(f, "x={}", self.coordinate),
+            ParseAxis::Y => write!(f, "y={}", self.coordinate)
+        }
+    }
+}
+
 fn solution13a(dots: &HashSet<Dot>, instructions: &[Fold]) -> usize {
     // Only care about the first instruction for part A
     let instruction = &instructions[0];
     
-    let new_dots = dots.iter()
-        .map(|dot| position_after_fold(dot, instruction))
-        // Collecting into a set will eliminate duplicates
-        .collect::<HashSet<Dot>>();
+    let (new_dots, _) = fold_paper(dots, &Paper::around(dots), instruction);
 
     new_dots.len()
 }
 
 fn solution13b(dots: &HashSet<Dot>, instructions: &[Fold]) -> String {
+    let new_dots = fold_all(dots, instructions);
+
+    // The folded dots should spell out letters, but show them as they are if they can't be read
+    read_letters(&new_dots).unwrap_or_else(|| render_dots(&new_dots))
+}
+
+fn fold_all(dots: &HashSet<Dot>, instructions: &[Fold]) -> HashSet<Dot> {
+    // Each fold depends on the size of the paper left by the one before, so the folds
+    // are applied one at a time to all the dots together
+    let (new_dots, _) = instructions.iter()
+        .fold((dots.clone(), Paper::around(dots)),
+            |(new_dots, paper), instruction| fold_paper(&new_dots, &paper, instruction)
+        );
+    new_dots
+}
+
+fn fold_paper(dots: &HashSet<Dot>, paper: &Paper, fold: &Fold) -> (HashSet<Dot>, Paper) {
     let new_dots = dots.iter()
-        .map(|dot| instructions.iter()
-            // Can apply all instructions by folding the entire list of them over
-            // the dot's original position
-            .fold(*dot,
-                |new_dot, instruction| position_after_fold(&new_dot, instruction)
-            )
-        )
+        .map(|dot| position_after_fold(dot, paper, fold))
+        // Collecting into a set will eliminate duplicates
         .collect::<HashSet<Dot>>();
+    (new_dots, fold.folded_paper(paper))
+}
 
+fn render_dots(new_dots: &HashSet<Dot>) -> String {
     // Determine the grid size for rendering the dots onto 
     let max_x = new_dots.iter().map(|(x, _y)| x).max().unwrap() + 1;
     let max_y = new_dots.iter().map(|(_x, y)| y).max().unwrap() + 1;
     let mut output = vec![vec![' '; max_x as usize]; max_y as usize];
 
     // Render all dots on to the grid
-    for (dot_x, dot_y) in new_dots {
+    for &(dot_x, dot_y) in new_dots {
         output[dot_y as usize][dot_x as usize] = '█';
     }
 
@@ -56,10 +128,115 @@ fn solution13b(dots: &HashSet<Dot>, instructions: &[Fold]) -> String {
         .join("\n")
 }
 
-fn position_after_fold((dot_x, dot_y): &Dot, fold: &Fold) -> Dot {
+// The dots and paper before each fold alongside that fold, then the dots and paper left once all
+// folds are done
+fn fold_stages<'a>(dots: &HashSet<Dot>, instructions: &'a [Fold]) -> Vec<(HashSet<Dot>, Paper, Option<&'a Fold>)> {
+    let mut stages = vec!((dots.clone(), Paper::around(dots), instructions.first()));
+    for (idx, instruction) in instructions.iter().enumerate() {
+        let (new_dots, paper) = fold_paper(&stages[idx].0, &stages[idx].1, instruction);
+        stages.push((new_dots, paper, instructions.get(idx + 1)));
+    }
+    stages
+}
+
+// Draw the paper in the style of the puzzle text, with dots as # and the fold line across it
+fn draw_paper(dots: &HashSet<Dot>, paper: &Paper, fold: Option<&Fold>) -> Vec<Vec<char>> {
+    // The fold line may sit on the edge of the paper, past any dots
+    let (width, height) = match fold {
+        Some(Fold {axis: ParseAxis::X, coordinate}) => (paper.width.max(coordinate + 1), paper.height),
+        Some(Fold {axis: ParseAxis::Y, coordinate}) => (paper.width, paper.height.max(coordinate + 1)),
+        None => (paper.width, paper.height)
+    };
+
+    let mut drawing = vec![vec!['.'; width as usize]; height as usize];
+    match fold {
+        Some(Fold {axis: ParseAxis::X, coordinate}) => drawing.iter_mut()
+            .for_each(|row| row[*coordinate as usize] = '|'),
+        Some(Fold {axis: ParseAxis::Y, coordinate}) => drawing[*coordinate as usize].iter_mut()
+            .for_each(|cell| *cell = '-'),
+        None => ()
+    }
+    for (dot_x, dot_y) in dots {
+        drawing[*dot_y as usize][*dot_x as usize] = '#';
+    }
+    drawing
+}
+
+fn print_fold_stages(dots: &HashSet<Dot>, instructions: &[Fold]) {
+    for (idx, (stage_dots, paper, fold)) in fold_stages(dots, instructions).iter().enumerate() {
+        match fold {
+            Some(fold) => println!("\nBefore fold {}, along {}:", idx + 1, fold),
+            None => println!("\nAfter all folds:")
+        }
+        for row in draw_paper(stage_dots, paper, *fold) {
+            println!("{}", row.iter().collect::<String>());
+        }
+    }
+}
+
+// Write the paper before each fold and after the last as a numbered sequence of plain PBM images.
+// Images are only black and white, so the fold line is dashed to tell it apart from the dots.
+// Returns the number of images.
+fn write_fold_stages(dots: &HashSet<Dot>, instructions: &[Fold], path_prefix: &str) -> io::Result<usize> {
+    let stages = fold_stages(dots, instructions);
+    for (idx, (stage_dots, paper, fold)) in stages.iter().enumerate() {
+        let drawing = draw_paper(stage_dots, paper, *fold);
+        let mut writer = BufWriter::new(File::create(format!("{}{:02}.pbm", path_prefix, idx))?);
+        writeln!(writer, "P1\n{} {}", drawing[0].len(), drawing.len())?;
+        for (y, row) in drawing.iter().enumerate() {
+            let pixels = row.iter()
+                .enumerate()
+                .map(|(x, cell)| match cell {
+                    '#' => "1",
+                    '.' => "0",
+                    _ => if (x + y) % 2 == 0 {"1"} else {"0"}
+                })
+                .collect::<Vec<&str>>();
+            // Plain PBM lines shouldn't run past 70 characters
+            for chunk in pixels.chunks(35) {
+                writeln!(writer, "{}", chunk.join(" "))?;
+            }
+        }
+        writer.flush()?;
+    }
+    Ok(stages.len())
+}
+
+// Recognise the dots as a row of capital letters in the puzzle font, with a column of space after
+// each letter. None if the dots don't line up as letters or any letter isn't in the font.
+fn read_letters(dots: &HashSet<Dot>) -> Option<String> {
+    if dots.iter().any(|&(x, y)| x < 0 || !(0..LETTER_HEIGHT).contains(&y)) {
+        return None;
+    }
+    let max_x = dots.iter().map(|(x, _y)| *x).max()?;
+
+    (0..=(max_x / LETTER_PITCH))
+        .map(|letter_idx| {
+            let left = letter_idx * LETTER_PITCH;
+            if (0..LETTER_HEIGHT).any(|y| dots.contains(&(left + LETTER_WIDTH, y))) {
+                return None;
+            }
+
+            let glyph = (0..LETTER_HEIGHT)
+                .map(|y| (left..(left + LETTER_WIDTH))
+                    .map(|x| if dots.contains(&(x, y)) {'#'} else {'.'})
+                    .collect::<String>()
+                )
+                .collect::<Vec<String>>();
+            LETTER_FONT.iter()
+                .find(|(_, rows)| rows.iter().copied().eq(glyph.iter().map(String::as_str)))
+                .map(|(letter, _)| *letter)
+        })
+        .collect()
+}
+
+fn position_after_fold((dot_x, dot_y): &Dot, paper: &Paper, fold: &Fold) -> Dot {
+    // Folding over a part bigger than the part kept would leave dots before the start
+    // of the paper, so everything is shifted along to start from zero again
+    let overhang = fold.overhang(paper);
     match fold.axis {
-        ParseAxis::X => (fold.coordinate - (fold.coordinate - *dot_x).abs(), *dot_y),
-        ParseAxis::Y => (*dot_x, fold.coordinate - (fold.coordinate - *dot_y).abs())
+        ParseAxis::X => (fold.coordinate - (fold.coordinate - *dot_x).abs() + overhang, *dot_y),
+        ParseAxis::Y => (*dot_x, fold.coordinate - (fold.coordinate - *dot_y).abs() + overhang)
     }
 }
 
@@ -90,4 +267,29 @@ fn parse_input(lines: Vec<String>) -> (HashSet<Dot>, Vec<Fold>) {
         .collect::<Vec<Fold>>();
     
         (dots, folds)
-}
\ No newline at end of file
+}
+
+const LETTER_WIDTH: i32 = 4;
+const LETTER_HEIGHT: i32 = 6;
+const LETTER_PITCH: i32 = LETTER_WIDTH + 1;
+
+// The letters that the puzzles draw with dots, row by row from the top
+const LETTER_FONT: [(char, [&str; LETTER_HEIGHT as usize]); 17] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"])
+];
@@ -1,44 +1,150 @@
-use crate::utils::read_string_lines;
+use crate::bignum::BigUint;
+use crate::utils::{flag_value, read_string_lines};
 
 use std::collections::{HashMap, HashSet};
-use std::cmp::{max, min};
+use std::collections::hash_map::Entry;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use itertools::Itertools;
+use regex::Regex;
 
 pub fn solution14 () {
-    let (template, children) = parse_input(read_string_lines("src/data/solution14.txt"));
+    let (template, children) = match parse_input(read_string_lines("src/data/solution14.txt")) {
+        Ok(input) => input,
+        Err(errors) => {
+            for error in errors {
+                println!("Invalid insertion rules: {}", error);
+            }
+            return;
+        }
+    };
+
+    if let Some(path) = flag_value("--export-dot") {
+        match write_rule_graph(&template, &children, &path) {
+            Ok(()) => println!("Wrote {} pair transitions to {}", children.len(), path),
+            Err(error) => println!("Unable to write rule graph to {}: {}", path, error)
+        }
+    }
+
     println!("{}", solution14a(&template, &children));
     println!("{}", solution14b(&template, &children));
+
+    if let Some(steps) = flag_value("--steps") {
+        let steps = steps.parse::<usize>().expect("Steps must be a non-negative number");
+        print_histogram(&template, &children, steps);
+    }
+
+    if let Some(steps) = flag_value("--expand") {
+        let steps = steps.parse::<usize>().expect("Steps must be a non-negative number");
+        if steps <= MAX_EXPANDED_STEPS {
+            check_expanded_polymers(&template, &children, steps);
+        } else {
+            println!("Polymers can only be expanded for up to {} steps", MAX_EXPANDED_STEPS);
+        }
+    }
 }
 
+// The polymer roughly doubles in length with each step, so writing it out in full is only
+// practical for the first few
+const MAX_EXPANDED_STEPS: usize = 20;
+// Longer polymers are summarised by their length rather than printed
+const MAX_PRINTED_LENGTH: usize = 100;
+
 // A polymer is a pair of characters
 type Polymer = (char, char);
 // A map of polymers to both expansions it will grow to
 type Children = HashMap<Polymer, [Polymer; 2]>;
-// A counter of character frequency
-type Counter = HashMap<char, usize>;
+// A counter of character frequency. Counts double with every iteration, so they're
+// kept exact without limit.
+type Counter = HashMap<char, BigUint>;
 // A counter for each polymer, created at each iteration based on the previous
 type Generation = HashMap<Polymer, Counter>;
 
-fn solution14a(template: &str, children: &Children) -> usize {
-    polymer_algorithm(template, children, 10)
+fn solution14a(template: &str, children: &Children) -> BigUint {
+    difference_most_least_common(&polymer_algorithm(template, children, 10))
 }
 
-fn solution14b(template: &str, children: &Children) -> usize {
-    polymer_algorithm(template, children, 40)
+fn solution14b(template: &str, children: &Children) -> BigUint {
+    difference_most_least_common(&polymer_algorithm(template, children, 40))
 }
 
-fn polymer_algorithm(template: &str, children: &Children, iterations: usize) -> usize {
+fn print_histogram(template: &str, children: &Children, iterations: usize) {
+    let counter = polymer_algorithm(template, children, iterations);
+    println!("After {} steps:", iterations);
+    for (character, count) in counter.iter().sorted() {
+        println!("{}: {}", character, count);
+    }
+    println!("Most minus least common: {}", difference_most_least_common(&counter));
+}
+
+// Grow the actual polymer string one step at a time, and make sure its letter counts match
+// the histogram derived from the generation maps at each step, stopping at the first mismatch
+fn check_expanded_polymers(template: &str, children: &Children, steps: usize) {
+    let mut polymer = template.to_string();
+    let mut generation = create_first_generation(children);
+
+    for step in 0..=steps {
+        if step > 0 {
+            polymer = expand_polymer(&polymer, children);
+            generation = progress_generation(&generation, children);
+        }
+        if polymer.len() <= MAX_PRINTED_LENGTH {
+            println!("After step {}: {}", step, polymer);
+        } else {
+            println!("After step {}: {} elements", step, polymer.len());
+        }
+
+        let mut counted = Counter::new();
+        for character in polymer.chars() {
+            let count = counted.entry(character).or_insert_with(BigUint::zero);
+            *count = &*count + &BigUint::from(1u64);
+        }
+        let histogram = calculate_final_counter(template, &generation);
+        if counted != histogram {
+            for character in counted.keys().chain(histogram.keys()).unique().sorted() {
+                let zero = BigUint::zero();
+                let (in_polymer, in_histogram) = (
+                    counted.get(character).unwrap_or(&zero),
+                    histogram.get(character).unwrap_or(&zero)
+                );
+                if in_polymer != in_histogram {
+                    println!("Mismatch at step {}: polymer has {} {}, histogram has {}", step, in_polymer, character, in_histogram);
+                }
+            }
+            return;
+        }
+    }
+    println!("Letter counts match the histogram for all {} steps", steps);
+}
+
+fn expand_polymer(polymer: &str, children: &Children) -> String {
+    // Each polymer is replaced by its first expansion, which ends in the inserted character.
+    // The second expansion starts with that character and ends with the start of the next
+    // polymer, so only the very last character needs adding back.
+    polymer.chars().zip(polymer.chars().skip(1))
+        .flat_map(|polymer| {
+            let (left, inserted) = children[&polymer][0];
+            [left, inserted]
+        })
+        .chain(polymer.chars().last())
+        .collect()
+}
+
+fn polymer_algorithm(template: &str, children: &Children, iterations: usize) -> Counter {
     // Create the counters for the character content of each base polymer at the
     // first generation, before any insertion iterations have happened
-    let mut generation_counters = vec![create_first_generation(children)];
+    let mut generation = create_first_generation(children);
 
     // We proceed through each iteration, calculating the histogram of characters that an
-    // original polymer would have expanded to by that iteration
-    for i in 0..iterations {
-        generation_counters.push(progress_generation(&generation_counters[i], children));
+    // original polymer would have expanded to by that iteration. Each generation is only
+    // derived from the one before, so earlier ones can be dropped as we go.
+    for _ in 0..iterations {
+        generation = progress_generation(&generation, children);
     }
 
-    // Apply the last generation map to our input string and perform output calculation
-    difference_most_least_common(calculate_final_counter(template, &generation_counters[iterations]))
+    // Apply the last generation map to our input string
+    calculate_final_counter(template, &generation)
 }
 
 fn create_first_generation(children_rules: &Children) -> Generation {
@@ -51,7 +157,7 @@ fn create_first_generation(children_rules: &Children) -> Generation {
     children_rules.keys()
         .map(|&polymer| {
             let mut counter_for_polymer = Counter::new();
-            counter_for_polymer.insert(polymer.0, 1);
+            counter_for_polymer.insert(polymer.0, BigUint::from(1u64));
             (
                 polymer,
                 counter_for_polymer
@@ -69,17 +175,16 @@ fn progress_generation(old_generation: &Generation, children: &Children) -> Gene
         .map(|(&polymer, _)|
             (
                 polymer,
-                children.get(&polymer).unwrap().iter()
-                    .map(|&child| old_generation.get(&child).unwrap())
-                    .cloned() // The reduction function will need full Generations instead of just refs
-                    .reduce(join_counters)
-                    .unwrap()
+                {
+                    let [left, right] = children.get(&polymer).unwrap();
+                    join_counters(old_generation.get(left).unwrap(), old_generation.get(right).unwrap())
+                }
             )
         )
         .collect::<Generation>()
 }
 
-fn join_counters(counter1: Counter, counter2: Counter) -> Counter {
+fn join_counters(counter1: &Counter, counter2: &Counter) -> Counter {
     // Create the unique set of keys over both counters
     let mut keys = counter1.keys().cloned().collect::<HashSet<char>>();
     for key in counter2.keys() {
@@ -90,10 +195,10 @@ fn join_counters(counter1: Counter, counter2: Counter) -> Counter {
 
     // For each key, add the count (if any) from each counter together
     for key in keys {
-        let mut count = 0;
-        for counter in [&counter1, &counter2] {
+        let mut count = BigUint::zero();
+        for counter in [counter1, counter2] {
             if counter.contains_key(&key) {
-                count += counter.get(&key).unwrap();
+                count = &count + counter.get(&key).unwrap();
             }
         }
 
@@ -108,51 +213,137 @@ fn calculate_final_counter(template: &str, generation: &Generation) -> Counter {
     // NOTE: array_windows() might be more elegant here but is not supported for chars()
     let mut final_counter = template.chars().zip(template[1..].chars())
         .map(|chars| generation[&chars].clone())
-        .reduce(join_counters)
+        .reduce(|counter1, counter2| join_counters(&counter1, &counter2))
         .unwrap();
 
     // The last character in the string is absent from all counters and so we
     // add it back in manually after all other processing
-    *final_counter
-        .get_mut(&template.chars()
+    let last_count = final_counter
+        .entry(template.chars()
             .last()
             .unwrap()
         )
-        .unwrap() += 1;
+        .or_insert_with(BigUint::zero);
+    *last_count = &*last_count + &BigUint::from(1u64);
 
     final_counter
 }
 
-fn difference_most_least_common(counter: Counter) -> usize {
-    let mut most = 0_usize;
-    let mut least = usize::MAX;
+fn difference_most_least_common(counter: &Counter) -> BigUint {
+    let (least, most) = counter.values()
+        .minmax()
+        .into_option()
+        .unwrap();
+
+    most.checked_sub(least).unwrap()
+}
 
-    for &value in counter.values() {
-        most = max(most, value);
-        least = min(least, value);
+// Write the pair transitions as a Graphviz DOT graph, with an edge from each polymer to both of
+// its expansions labelled by the inserted character. Polymers found in the template are the
+// starting points of growth, so they're highlighted.
+fn write_rule_graph(template: &str, children: &Children, path: &str) -> io::Result<()> {
+    let starting = template.chars().zip(template.chars().skip(1)).collect::<HashSet<Polymer>>();
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "digraph polymers {{")?;
+    for polymer in children.keys().sorted() {
+        let style = if starting.contains(polymer) {", style=filled, fillcolor=lightblue"} else {""};
+        writeln!(writer, "    {0}{1} [label=\"{0}{1}\"{2}];", polymer.0, polymer.1, style)?;
     }
+    for (polymer, [left, right]) in children.iter().sorted() {
+        for child in [left, right] {
+            writeln!(writer, "    {}{} -> {}{} [label=\"{}\"];", polymer.0, polymer.1, child.0, child.1, left.1)?;
+        }
+    }
+    writeln!(writer, "}}")?;
+    writer.flush()
+}
 
-    most - least
+// Problems with the insertion rules that would stop polymers from being grown
+#[derive(Debug)]
+enum RuleError {
+    // The template must have at least one pair for the polymer to grow from
+    Template {text: String},
+    // Line numbers count from 1 at the template
+    Separator {line: usize, text: String},
+    Malformed {line: usize, text: String},
+    Duplicate {line: usize, polymer: Polymer},
+    // A polymer from the template or grown by a rule that no rule expands
+    Missing {polymer: Polymer}
 }
 
-fn parse_input(lines: Vec<String>) -> (String, Children) {
-    let template = lines[0].clone();
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Template {text} =>
+                write!(f, "line 1 should be a template of at least two letters A-Z but was \"{}\"", text),
+            Self::Separator {line, text} =>
+                write!(f, "line {} should be blank, separating the template from the rules, but was \"{}\"", line, text),
+            Self::Malformed {line, text} =>
+                write!(f, "line {} should look like \"AB -> C\" but was \"{}\"", line, text),
+            Self::Duplicate {line, polymer} =>
+                write!(f, "line {} gives a second rule for {}{}", line, polymer.0, polymer.1),
+            Self::Missing {polymer} =>
+                write!(f, "no rule for {}{}, which the template or another rule produces", polymer.0, polymer.1)
+        }
+    }
+}
+
+fn parse_input(lines: Vec<String>) -> Result<(String, Children), Vec<RuleError>> {
+    let template = lines.first().cloned().unwrap_or_default();
+    let rule_format = Regex::new(r"^([A-Z])([A-Z]) -> ([A-Z])$").unwrap();
+    let mut errors = Vec::<RuleError>::new();
+
+    let template_valid = template.len() >= 2 && template.chars().all(|c| c.is_ascii_uppercase());
+    if !template_valid {
+        errors.push(RuleError::Template {text: template.clone()});
+    }
+    if let Some(separator) = lines.get(1).filter(|line| !line.is_empty()) {
+        errors.push(RuleError::Separator {line: 2, text: separator.clone()});
+    }
     
-    let children = lines.iter()
-        // Begin reading from 3rd line to get ruleset
+    let mut children = Children::new();
+    for (idx, rule) in lines.iter()
+        .enumerate()
+        // Begin reading from 3rd line to get ruleset, where every non-empty line must be a rule
         .skip(2)
-        .take_while(|line| !line.is_empty())
-        // Positions of characters of interest are the same for every line
-        .map(|rule| {
-            let left_char = rule.chars().nth(0).unwrap();
-            let mid_char = rule.chars().nth(6).unwrap();
-            let right_char = rule.chars().nth(1).unwrap();
-            (
-                (left_char, right_char),
-                [(left_char, mid_char), (mid_char, right_char)]
-            )
-        })
-        .collect::<Children>();
-    
-        (template, children)
+        .filter(|(_, line)| !line.is_empty())
+    {
+        let line = idx + 1;
+        let captures = match rule_format.captures(rule) {
+            Some(captures) => captures,
+            None => {
+                errors.push(RuleError::Malformed {line, text: rule.clone()});
+                continue;
+            }
+        };
+        let [left_char, right_char, mid_char] = [1, 2, 3]
+            .map(|group| captures[group].chars().next().unwrap());
+
+        let polymer = (left_char, right_char);
+        match children.entry(polymer) {
+            Entry::Occupied(_) => errors.push(RuleError::Duplicate {line, polymer}),
+            Entry::Vacant(entry) => {
+                entry.insert([(left_char, mid_char), (mid_char, right_char)]);
+            }
+        }
+    }
+
+    // Every polymer that can ever appear must itself have a rule to grow by. An invalid template
+    // has already been reported, so only the rules' own polymers are checked against it.
+    let template_pairs = template.chars().zip(template.chars().skip(1))
+        .filter(|_| template_valid);
+    let needed = template_pairs
+        .chain(children.values().flatten().copied())
+        .collect::<HashSet<Polymer>>();
+    errors.extend(needed.into_iter()
+        .filter(|polymer| !children.contains_key(polymer))
+        .sorted()
+        .map(|polymer| RuleError::Missing {polymer})
+    );
+
+    if errors.is_empty() {
+        Ok((template, children))
+    } else {
+        Err(errors)
+    }
 }
\ No newline at end of file
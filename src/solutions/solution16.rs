@@ -1,134 +1,262 @@
-use crate::utils::read_string_lines;
+use crate::bignum::BigUint;
+use crate::utils::{has_flag, read_string_lines};
+
+use std::fmt;
 
 pub fn solution16 () {
     let code_str = read_string_lines("src/data/solution16.txt").remove(0);
-    let bit_string = parse_bytes(&code_str);
     // Can parse the packet now to provide to sub-problems
-    let root_packet = parse_packet(&bit_string);
-    println!("{}", solution16a(&root_packet));
-    println!("{}", solution16b(&root_packet));
+    match parse_transmission(&code_str) {
+        Ok(root_packet) => {
+            println!("{}", solution16a(&root_packet));
+            // Exact evaluation can't overflow, but is slower than the checked 128-bit version
+            if has_flag("--exact") {
+                println!("{}", solution16b_exact(&root_packet));
+            } else {
+                match solution16b(&root_packet) {
+                    Ok(value) => println!("{}", value),
+                    Err(error) => println!("Unable to evaluate transmission: {}", error)
+                }
+            }
+        },
+        Err(error) => println!("Unable to parse transmission: {}", error)
+    }
 }
 
 fn solution16a(root_packet: &Packet) -> u32 {
     root_packet.version_sum()
 }
 
-fn solution16b(root_packet: &Packet) -> u128 {
+fn solution16b(root_packet: &Packet) -> Result<u128, OverflowError> {
     root_packet.eval()
 }
 
-// Convert from hex to bitstring (in this case, vector of bools)
-fn parse_bytes(code_str: &String) -> Vec<bool> {
-    hex::decode(code_str).expect("Supplied code isn't valid hex").iter()
-        // Flatmap to convert each byte in stream to concatenated sub-stream of 8 bits
-        .flat_map(|byte| [
-            byte & 128 == 128, byte & 64 == 64, byte & 32 == 32, byte & 16 == 16,
-            byte &   8 ==   8, byte &  4 ==  4, byte &  2 ==  2, byte &  1 ==  1
-        ])
-        .collect::<Vec<bool>>()
+fn solution16b_exact(root_packet: &Packet) -> BigUint {
+    root_packet.eval().expect("Arbitrary precision evaluation cannot overflow")
+}
+
+fn parse_transmission(code_str: &str) -> Result<Packet, ParseError> {
+    let mut reader = BitReader::new(parse_bytes(code_str)?);
+    let root_packet = parse_packet(&mut reader)?;
+
+    // Anything after the root packet should only be padding out to the final hex digit
+    match reader.next_set_bit() {
+        Some(offset) => Err(ParseError {
+            offset,
+            reason: ParseErrorReason::NonZeroPadding
+        }),
+        None => Ok(root_packet)
+    }
+}
+
+// Convert from hex to the raw bytes of the transmission
+fn parse_bytes(code_str: &str) -> Result<Vec<u8>, ParseError> {
+    hex::decode(code_str).map_err(|error| ParseError {
+        // Each hex character accounts for four bits
+        offset: match error {
+            hex::FromHexError::InvalidHexCharacter { index, .. } => index * 4,
+            _ => code_str.len() * 4
+        },
+        reason: ParseErrorReason::InvalidHex(error)
+    })
+}
+
+// Read a field from the reader, failing with the supplied reason if the transmission ends
+// before the field does
+fn read_field(reader: &mut BitReader, width: usize, reason: ParseErrorReason) -> Result<u64, ParseError> {
+    reader.read(width)
+        .ok_or(ParseError {offset: reader.position, reason})
 }
 
-// Parse a specified number of bits into a numeric value 
-fn read_num(bit_string: &[bool], length: usize) -> u32 {
-    let mut output = 0u32;
-    
-    for (idx, bit) in bit_string.iter().enumerate().take(length) {
-        if *bit {
-            output += 1 << (length - idx - 1);
+// Packets can be nested arbitrarily deeply, so rather than recursing into subpackets, operator
+// packets still waiting on subpackets are kept on a stack. Each packet finished is handed to the
+// operator on top, and any operators that then have all their subpackets are finished in turn.
+fn parse_packet(reader: &mut BitReader) -> Result<Packet, ParseError> {
+    let mut open_operators = Vec::<OpenOperator>::new();
+    loop {
+        let start = reader.position;
+        let version = read_field(reader, 3, ParseErrorReason::TruncatedHeader)? as u8;
+        let operator_num = read_field(reader, 3, ParseErrorReason::TruncatedHeader)?;
+
+        // Compare on operator to determine the type of patcket this is
+        let mut finished = if operator_num == 4 {
+            let data = read_literal(reader)?;
+            Some(Packet {
+                version, data,
+                offset: start,
+                bit_length: reader.position - start
+            })
+        } else {
+            open_operators.push(open_operator(reader, start, version, match operator_num {
+                0 => Operator::Sum,
+                1 => Operator::Product,
+                2 => Operator::Minimum,
+                3 => Operator::Maximum,
+                5 => Operator::Greater,
+                6 => Operator::Lesser,
+                7 => Operator::Equal,
+                _ => unreachable!("Operator numbers are only three bits wide")
+            })?);
+            None
+        };
+
+        loop {
+            if let Some(packet) = finished.take() {
+                match open_operators.last_mut() {
+                    Some(parent) => {
+                        // Update totals and add parsed packed to subpacket list
+                        parent.total_bit_length += packet.bit_length;
+                        parent.subpackets.push(packet);
+                    },
+                    None => return Ok(packet)
+                }
+            }
+            match open_operators.last() {
+                Some(operator) if operator.has_all_subpackets() =>
+                    finished = Some(open_operators.pop().unwrap().finish(reader)?),
+                _ => break
+            }
         }
     }
-    output
 }
 
-fn parse_packet(bit_string: &[bool]) -> Packet {
-    let version = read_num(bit_string, 3) as u8;
-    let operator_num = read_num(&bit_string[3..], 3);
+// An operator packet whose subpackets are still being read
+struct OpenOperator {
+    version: u8,
+    offset: usize,
+    operator: Operator,
+    length: Length,
+    // Track both subpackets and bits read to allow stopping on either strategy
+    subpackets: Vec<Packet>,
+    total_bit_length: usize
+}
 
-    // Compare on operator to determine the type of patcket this is
-    let (data, data_length) = if operator_num == 4 {
-        read_literal(&bit_string[6..])
+fn open_operator(reader: &mut BitReader, start: usize, version: u8, operator: Operator) -> Result<OpenOperator, ParseError> {
+    let length = if read_field(reader, 1, ParseErrorReason::TruncatedHeader)? == 1 {
+        // First bit ON indicates number of subpackets
+        Length::Subpackets(read_field(reader, 11, ParseErrorReason::TruncatedHeader)? as usize)
     } else {
-        parse_subpackets(&bit_string[6..], match operator_num {
-            0 => Operator::Sum,
-            1 => Operator::Product,
-            2 => Operator::Minimum,
-            3 => Operator::Maximum,
-            5 => Operator::Greater,
-            6 => Operator::Lesser,
-            7 => Operator::Equal,
-            _ => panic!("Invalid operator found!")
-        })
+        // First bit OFF indicates total bit length
+        Length::TotalBits(read_field(reader, 15, ParseErrorReason::TruncatedHeader)? as usize)
     };
 
-    Packet{
-        version, data,
-        bit_length: data_length + 6
+    Ok(OpenOperator {
+        version,
+        offset: start,
+        operator,
+        length,
+        subpackets: Vec::<Packet>::new(),
+        total_bit_length: 0
+    })
+}
+
+impl OpenOperator {
+    fn has_all_subpackets(&self) -> bool {
+        // The condition to stop reading depends on the length strategy
+        match self.length {
+            Length::TotalBits(bits) => self.total_bit_length >= bits,
+            Length::Subpackets(packets) => self.subpackets.len() >= packets
+        }
+    }
+
+    fn finish(self, reader: &BitReader) -> Result<Packet, ParseError> {
+        // Subpackets must finish exactly on the declared bit length rather than overrunning it
+        if let Length::TotalBits(bits) = self.length {
+            if self.total_bit_length != bits {
+                return Err(ParseError {
+                    offset: reader.position - self.total_bit_length + bits,
+                    reason: ParseErrorReason::BadLength {declared: bits, read: self.total_bit_length}
+                });
+            }
+        }
+
+        // Comparisons need exactly two operands, and minimum/maximum need at least one
+        if !self.operator.accepts_operand_count(self.subpackets.len()) {
+            return Err(ParseError {
+                offset: self.offset,
+                reason: ParseErrorReason::OperandCount {operator: self.operator, found: self.subpackets.len()}
+            });
+        }
+
+        Ok(Packet {
+            version: self.version,
+            offset: self.offset,
+            bit_length: reader.position - self.offset,
+            data: Data::Operator(OperatorPacket {operator: self.operator, subpackets: self.subpackets})
+        })
     }
 }
 
 // Use 5-bit batch parsing method to read literal values
-fn read_literal(bit_string: &[bool]) -> (Data, usize) {
-    let mut groups = Vec::<u64>::new();
-    let mut cur_index = 0;
+fn read_literal(reader: &mut BitReader) -> Result<Data, ParseError> {
+    let mut value = Literal::Small(0);
+    let group_scale = BigUint::from(16u64);
 
     loop {
-        groups.push(read_num(&bit_string[cur_index+1..], 4) as u64);
+        let group = read_field(reader, 5, ParseErrorReason::TruncatedLiteral)?;
+        let digit = group & 0b1111;
+        value = match value {
+            // Shifting in another four bits is only safe while the top four are clear
+            Literal::Small(small) if small >> 124 == 0 => Literal::Small((small << 4) | digit as u128),
+            Literal::Small(small) => Literal::Wide(&(&BigUint::from(small) * &group_scale) + &BigUint::from(digit)),
+            Literal::Wide(wide) => Literal::Wide(&(&wide * &group_scale) + &BigUint::from(digit))
+        };
         // Only proceed to next batch if leading bit is 1
-        if !bit_string[cur_index] {
+        if group & 0b10000 == 0 {
             break;
         }
-        cur_index += 5;
     }
 
-    let data = Data::Literal(
-        groups.iter()
-            .copied()
-            .reduce(|acc, group| (acc << 4) + group)
-            .expect("Literal data had no groups")
-    );
+    Ok(Data::Literal(value))
+}
 
-    (data, groups.len() * 5)
-    
+// A cursor over the bytes of the transmission that reads fields of any width up to 64 bits,
+// without needing to expand every bit of the input up front
+struct BitReader {
+    bytes: Vec<u8>,
+    // Number of bits already read from the start of the transmission
+    position: usize
 }
 
-fn parse_subpackets(bit_string: &[bool], operator: Operator) -> (Data, usize) {
-    let length = if bit_string[0] {
-        // First bit ON indicates number of subpackets
-        Length::Subpackets(read_num(&bit_string[1..], 11) as usize)
-    } else {
-        // First bit OFF indicates total bit length
-        Length::TotalBits(read_num(&bit_string[1..], 15) as usize)
-    };
+impl BitReader {
+    fn new(bytes: Vec<u8>) -> Self {
+        Self { bytes, position: 0 }
+    }
 
-    let mut subpackets = Vec::<Packet>::new();
+    fn remaining(&self) -> usize {
+        self.bytes.len() * 8 - self.position
+    }
 
-    // Track both subpackets and bits read to allow stopping on either strategy
-    let mut total_bit_length: usize = 0;
-    let mut subpacket_count: usize = 0;
-    let header_bits = 1 + if let Length::TotalBits(_) = length {15} else {11};
+    // Read the next bits as a number, most significant bit first, or None if there aren't enough
+    // bits left. Whole runs of bits within each byte are taken at once rather than bit by bit.
+    fn read(&mut self, width: usize) -> Option<u64> {
+        if width > 64 || width > self.remaining() {
+            return None;
+        }
 
-    loop {
-        let subpacket = parse_packet(&bit_string[(header_bits+total_bit_length)..]);
+        let mut output = 0u64;
+        let mut bits_left = width;
 
-        // Update totals and add parsed packed to subpacket list
-        total_bit_length += subpacket.bit_length;
-        subpacket_count += 1;
-        subpackets.push(subpacket);
+        while bits_left > 0 {
+            let byte = self.bytes[self.position / 8];
+            let unread_in_byte = 8 - self.position % 8;
+            let take = bits_left.min(unread_in_byte);
 
-        // The condition to stop reading depends on the length strategy
-        if match length {
-            Length::TotalBits(bits) => total_bit_length == bits,
-            Length::Subpackets(packets) => subpacket_count == packets
-        } {
-            break;
+            // Shift the wanted bits to the bottom of the byte and mask off any read earlier
+            let chunk = (byte >> (unread_in_byte - take)) as u64 & ((1 << take) - 1);
+            output = (output << take) | chunk;
+
+            self.position += take;
+            bits_left -= take;
         }
+        Some(output)
     }
 
-    let data = Data::Operator(
-        OperatorPacket { operator, subpackets }
-    );
-
-    (data, header_bits + total_bit_length)
+    // Position of the first set bit at or after the cursor, if any
+    fn next_set_bit(&self) -> Option<usize> {
+        (self.position..self.bytes.len() * 8)
+            .find(|&idx| self.bytes[idx / 8] & (128 >> (idx % 8)) != 0)
+    }
 }
 
 #[derive(PartialEq)]
@@ -140,17 +268,26 @@ enum Length {
 }
 
 enum Data {
-    // Some literal values exceed unsigned 32-bit representation
-    Literal(u64),
+    Literal(Literal),
     Operator(OperatorPacket)
 }
 
+// Literals can have any number of groups, but are only kept at arbitrary precision when they
+// don't fit in 128 bits
+enum Literal {
+    Small(u128),
+    Wide(BigUint)
+}
+
 struct Packet {
     version: u8,
+    // Position of the packet's first bit in the transmission
+    offset: usize,
     bit_length: usize,
     data: Data
 }
 
+#[derive(Debug)]
 enum Operator {Sum, Product, Minimum, Maximum, Greater, Lesser, Equal}
 
 struct OperatorPacket {
@@ -158,37 +295,266 @@ struct OperatorPacket {
     subpackets: Vec<Packet>
 }
 
+// Dropping nested packets would normally recurse once per level, so subpackets are moved out onto
+// a single list and dropped from there, each with no subpackets left of its own
+impl Drop for OperatorPacket {
+    fn drop(&mut self) {
+        let mut pending = std::mem::take(&mut self.subpackets);
+        while let Some(mut packet) = pending.pop() {
+            if let Data::Operator(operator_packet) = &mut packet.data {
+                pending.append(&mut operator_packet.subpackets);
+            }
+        }
+    }
+}
+
+// The next step while evaluating packets: either a packet to evaluate, or the value of the one
+// just evaluated
+enum EvalStep<'a, V> {
+    Enter(&'a Packet),
+    Finished(V)
+}
+
+// An operator packet being evaluated, with the values of its subpackets so far
+struct EvalFrame<'a, V> {
+    offset: usize,
+    operator_packet: &'a OperatorPacket,
+    values: Vec<V>
+}
+
+#[derive(Debug)]
+struct ParseError {
+    // Bit offset into the transmission where the problem was found
+    offset: usize,
+    reason: ParseErrorReason
+}
+
+#[derive(Debug)]
+enum ParseErrorReason {
+    // The supplied code couldn't be decoded as hex
+    InvalidHex(hex::FromHexError),
+    // The transmission ended partway through a packet header
+    TruncatedHeader,
+    // The transmission ended partway through the groups of a literal value
+    TruncatedLiteral,
+    // Subpackets didn't finish on the total bit length declared by their operator packet
+    BadLength {declared: usize, read: usize},
+    // Bits after the root packet should all be zero
+    NonZeroPadding,
+    // An operator packet had the wrong number of subpackets for its operator
+    OperandCount {operator: Operator, found: usize}
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bit {}: ", self.offset)?;
+        match &self.reason {
+            ParseErrorReason::InvalidHex(error) => write!(f, "invalid hex ({})", error),
+            ParseErrorReason::TruncatedHeader => write!(f, "truncated packet header"),
+            ParseErrorReason::TruncatedLiteral => write!(f, "truncated literal value"),
+            ParseErrorReason::BadLength {declared, read} =>
+                write!(f, "subpackets declared {} bits but read {}", declared, read),
+            ParseErrorReason::NonZeroPadding => write!(f, "non-zero padding after root packet"),
+            ParseErrorReason::OperandCount {operator, found} =>
+                write!(f, "{:?} operator given {} operands", operator, found)
+        }
+    }
+}
+
+impl Operator {
+    fn accepts_operand_count(&self, count: usize) -> bool {
+        match self {
+            Operator::Sum | Operator::Product => true,
+            Operator::Minimum | Operator::Maximum => count >= 1,
+            Operator::Greater | Operator::Lesser | Operator::Equal => count == 2
+        }
+    }
+}
+
 impl Packet {
     fn version_sum(&self) -> u32 {
-        (self.version as u32)
-        + match &self.data {
-            Data::Literal(_) => 0,
-            Data::Operator(packet) => packet.subpackets.iter()
-                .map(|subpacket| subpacket.version_sum())
-                .sum()
-        }
-    }
-
-    fn eval(&self) -> u128 {
-        match &self.data {
-            // Literals need no operation, just return their value
-            // 128-bit needed for handling large products
-            Data::Literal(value) => *value as u128,
-            Data::Operator(operator_packet) => {
-                // Prepare (lazy-evaluated) evaluations of subpackets in advance for use by the
-                // possible operators
-                let values = operator_packet.subpackets.iter().map(|packet| packet.eval());
-
-                match &operator_packet.operator {
-                    Operator::Sum => values.sum(),
-                    Operator::Product => values.product(),
-                    Operator::Minimum => values.min().expect("No subpackets!"),
-                    Operator::Maximum => values.max().expect("No subpackets!"),
-                    Operator::Greater => values.take(2).reduce(|first, second| if first > second {1} else {0}).expect("Not enough subpackets!"),
-                    Operator::Lesser => values.take(2).reduce(|first, second| if first < second {1} else {0}).expect("Not enough subpackets!"),
-                    Operator::Equal => values.take(2).reduce(|first, second| if first == second {1} else {0}).expect("Not enough subpackets!")
-                }
+        // Packets still to be counted, to avoid recursing through deeply nested packets
+        let mut pending = vec![self];
+        let mut sum = 0;
+        while let Some(packet) = pending.pop() {
+            sum += packet.version as u32;
+            if let Data::Operator(operator_packet) = &packet.data {
+                pending.extend(operator_packet.subpackets.iter());
             }
         }
+        sum
     }
-}
\ No newline at end of file
+
+    // Evaluation is generic over the value type so the same rules serve both checked
+    // primitive arithmetic and exact arbitrary precision arithmetic. Operator packets waiting on
+    // their subpackets are kept on a stack rather than recursed into, as nesting can be very deep.
+    fn eval<V: PacketValue>(&self) -> Result<V, OverflowError> {
+        let mut frames = Vec::<EvalFrame<V>>::new();
+        let mut step = EvalStep::Enter(self);
+        loop {
+            step = match step {
+                EvalStep::Enter(packet) => match &packet.data {
+                    // Literals need no operation, just return their value
+                    Data::Literal(value) => EvalStep::Finished(
+                        V::from_literal(value).ok_or(OverflowError { offset: packet.offset })?
+                    ),
+                    Data::Operator(operator_packet) => {
+                        frames.push(EvalFrame {
+                            offset: packet.offset,
+                            operator_packet,
+                            values: Vec::with_capacity(operator_packet.subpackets.len())
+                        });
+                        Self::next_eval_step(&mut frames)?
+                    }
+                },
+                EvalStep::Finished(value) => match frames.last_mut() {
+                    Some(frame) => {
+                        frame.values.push(value);
+                        Self::next_eval_step(&mut frames)?
+                    },
+                    None => return Ok(value)
+                }
+            };
+        }
+    }
+
+    // Move on to the next subpacket of the innermost operator, or apply the operator once all
+    // its subpackets have values
+    fn next_eval_step<'a, V: PacketValue>(frames: &mut Vec<EvalFrame<'a, V>>) -> Result<EvalStep<'a, V>, OverflowError> {
+        let frame = frames.last().unwrap();
+        let subpackets = &frame.operator_packet.subpackets;
+        if frame.values.len() < subpackets.len() {
+            return Ok(EvalStep::Enter(&subpackets[frame.values.len()]));
+        }
+
+        let frame = frames.pop().unwrap();
+        frame.operator_packet.apply(frame.values, frame.offset).map(EvalStep::Finished)
+    }
+}
+
+impl OperatorPacket {
+    fn apply<V: PacketValue>(&self, values: Vec<V>, offset: usize) -> Result<V, OverflowError> {
+        let overflow = || OverflowError { offset };
+
+        // The parser guarantees that each operator has a valid number of operands
+        let comparison = |compare: fn(&V, &V) -> bool| V::from_bool(compare(&values[0], &values[1]));
+
+        match &self.operator {
+            Operator::Sum => values.into_iter()
+                .try_fold(V::from_bool(false), |acc, value| acc.checked_add(&value))
+                .ok_or_else(overflow),
+            Operator::Product => values.into_iter()
+                .try_fold(V::from_bool(true), |acc, value| acc.checked_mul(&value))
+                .ok_or_else(overflow),
+            Operator::Minimum => Ok(values.into_iter().min().expect("No subpackets!")),
+            Operator::Maximum => Ok(values.into_iter().max().expect("No subpackets!")),
+            Operator::Greater => Ok(comparison(|first, second| first > second)),
+            Operator::Lesser => Ok(comparison(|first, second| first < second)),
+            Operator::Equal => Ok(comparison(|first, second| first == second))
+        }
+    }
+}
+
+// Reports the packet whose value couldn't be represented, by its position in the transmission
+#[derive(Debug)]
+struct OverflowError {
+    offset: usize
+}
+
+impl fmt::Display for OverflowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "value of packet at bit {} overflowed", self.offset)
+    }
+}
+
+// Arithmetic needed to evaluate packets, where a None result indicates an overflow
+trait PacketValue: Sized + Ord {
+    fn from_literal(literal: &Literal) -> Option<Self>;
+    // Comparison operators give one or zero, which double as the additive and multiplicative identities
+    fn from_bool(value: bool) -> Self;
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+}
+
+impl PacketValue for u128 {
+    fn from_literal(literal: &Literal) -> Option<Self> {
+        match literal {
+            Literal::Small(value) => Some(*value),
+            Literal::Wide(_) => None
+        }
+    }
+    fn from_bool(value: bool) -> Self { value as u128 }
+    fn checked_add(&self, other: &Self) -> Option<Self> { u128::checked_add(*self, *other) }
+    fn checked_mul(&self, other: &Self) -> Option<Self> { u128::checked_mul(*self, *other) }
+}
+
+impl PacketValue for BigUint {
+    fn from_literal(literal: &Literal) -> Option<Self> {
+        match literal {
+            Literal::Small(value) => Some(BigUint::from(*value)),
+            Literal::Wide(value) => Some(value.clone())
+        }
+    }
+    fn from_bool(value: bool) -> Self { BigUint::from(value as u64) }
+    fn checked_add(&self, other: &Self) -> Option<Self> { Some(self + other) }
+    fn checked_mul(&self, other: &Self) -> Option<Self> { Some(self * other) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(code_str: &str) -> ParseError {
+        match parse_transmission(code_str) {
+            Ok(_) => panic!("{} should not parse", code_str),
+            Err(error) => error
+        }
+    }
+
+    #[test]
+    fn invalid_hex_is_reported_at_its_digit() {
+        let error = parse_error("3G");
+        assert_eq!(error.offset, 4);
+        assert!(matches!(error.reason, ParseErrorReason::InvalidHex(_)));
+    }
+
+    // A Lesser operator whose 15-bit length field runs past the end of the transmission
+    #[test]
+    fn truncated_header_is_reported_where_the_field_starts() {
+        let error = parse_error("3800");
+        assert_eq!(error.offset, 7);
+        assert!(matches!(error.reason, ParseErrorReason::TruncatedHeader));
+    }
+
+    // The puzzle example 38006F45291200 cut short during the groups of its second literal
+    #[test]
+    fn truncated_literal_is_reported_at_the_missing_group() {
+        let error = parse_error("38006F452912");
+        assert_eq!(error.offset, 44);
+        assert!(matches!(error.reason, ParseErrorReason::TruncatedLiteral));
+    }
+
+    // A Sum operator declaring 10 bits of subpackets, holding an 11-bit literal starting at bit 22
+    #[test]
+    fn bad_length_is_reported_where_the_subpackets_should_have_ended() {
+        let error = parse_error("0000284080");
+        assert_eq!(error.offset, 32);
+        assert!(matches!(error.reason, ParseErrorReason::BadLength {declared: 10, read: 11}));
+    }
+
+    // An 11-bit literal followed by a set bit in its padding
+    #[test]
+    fn non_zero_padding_is_reported_at_the_set_bit() {
+        let error = parse_error("1024");
+        assert_eq!(error.offset, 13);
+        assert!(matches!(error.reason, ParseErrorReason::NonZeroPadding));
+    }
+
+    // A Sum operator holding a Greater operator at bit 18 with only one operand
+    #[test]
+    fn operand_count_is_reported_at_the_operator_packet() {
+        let error = parse_error("020045801102");
+        assert_eq!(error.offset, 18);
+        assert!(matches!(error.reason, ParseErrorReason::OperandCount {operator: Operator::Greater, found: 1}));
+    }
+}
@@ -1,81 +1,293 @@
-use crate::utils::read_string_lines;
+use crate::utils::{flag_value, has_flag, read_string_lines};
 use regex::Regex;
 use itertools::Itertools;
+use std::ops::RangeInclusive;
+
+// Largest plot drawn in the terminal, in characters. Larger trajectories are scaled down to fit.
+const PLOT_WIDTH: i64 = 120;
+const PLOT_HEIGHT: i64 = 50;
 
 pub fn solution17 () {
     let target_area = parse_target_area(&read_string_lines("src/data/solution17.txt").remove(0));
-    println!("{}", solution17a(&target_area));
-    println!("{}", solution17b(&target_area));
-}
-
-fn solution17a(target_area: &TargetArea) -> i32 {
-    // Search begins from maximum y velocity down to zero. The maximum
-    // considered is the distance between the origin and the bottom of
-    // the target area. Velocities larger than this are likely to skip
-    // over the entire target area in one step.
-    for y_vel in (target_area.min_y..=(-target_area.min_y)).rev() {
-        // Search from 0 x velocity to a value that would skip past the
-        // target area in a single step
-        for x_vel in 0..=(target_area.max_x) {
-            if probe_ends_within_area(
-                Probe {x_pos: 0, y_pos: 0, x_vel, y_vel},
-                target_area
-            ) {
-                return height_for_initial_y_vel(y_vel);
-            }
+
+    if let Some(velocity) = flag_value("--plot") {
+        let vels = velocity.split(',')
+            .map(|vel| vel.parse::<i32>().expect("Plot velocity components must be integers"))
+            .collect::<Vec<i32>>();
+        assert!(vels.len() == 2, "Plot velocity must be given as x_vel,y_vel");
+        plot_trajectory(Probe {x_pos: 0, y_pos: 0, x_vel: vels[0] as i64, y_vel: vels[1] as i64}, &target_area);
+    }
+
+    let velocities = match hitting_velocities(&target_area) {
+        Some(velocities) => velocities,
+        None => {
+            println!("Infinitely many launch velocities hit a target area level with the launcher that a probe can come to rest above or below");
+            return;
         }
+    };
+    println!("{}", solution17a(&velocities));
+    println!("{}", solution17b(&velocities));
+
+    if has_flag("--list") {
+        list_velocities(&velocities);
     }
-    panic!("Couldn't find initial velocities that ended up within the target area")
 }
 
-fn solution17b(target_area: &TargetArea) -> i32 {
-    let mut success_count = 0;
+fn solution17a(velocities: &[(i32, i32)]) -> i32 {
+    // The highest trajectory comes from the largest y velocity that hits
+    velocities.iter()
+        .map(|&(_, y_vel)| height_for_initial_y_vel(y_vel))
+        .max()
+        .expect("Couldn't find initial velocities that ended up within the target area")
+}
 
-    // Same search criteria as in part A
-    // Could also be done as an iterator stream and filter/count
-    for y_vel in target_area.min_y..=(-target_area.min_y) {
-        for x_vel in 0..=(target_area.max_x) {
-            if probe_ends_within_area(
-                Probe {x_pos: 0, y_pos: 0, x_vel, y_vel},
-                target_area
-            ) {
-                success_count += 1;
-            }
-        }
+fn solution17b(velocities: &[(i32, i32)]) -> usize {
+    velocities.len()
+}
+
+fn list_velocities(velocities: &[(i32, i32)]) {
+    for &(x_vel, y_vel) in velocities {
+        println!("{},{} reaches a height of {}", x_vel, y_vel, height_for_initial_y_vel(y_vel));
+    }
+}
+
+// Find every launch velocity that leaves the probe within the target area after some step. Rather
+// than simulating, each x velocity gives a range of steps where the probe is horizontally within the
+// target, and each y velocity the steps where it's vertically within. A velocity hits exactly when
+// these overlap.
+fn hitting_velocities(target_area: &TargetArea) -> Option<Vec<(i32, i32)>> {
+    let (x_vels, y_vels) = velocity_bounds(target_area)?;
+    let y_ranges = y_vels
+        .map(|y_vel| (y_vel, y_step_ranges(y_vel, target_area)))
+        .filter(|(_, steps)| !steps.is_empty())
+        .collect::<Vec<(i32, Vec<StepRange>)>>();
+
+    Some(x_vels
+        .filter_map(|x_vel| x_step_range(x_vel, target_area).map(|steps| (x_vel, steps)))
+        .flat_map(|(x_vel, x_steps)| y_ranges.iter()
+            .filter(move |(_, y_steps)| y_steps.iter().any(|y_steps| x_steps.overlaps(y_steps)))
+            .map(move |&(y_vel, _)| (x_vel, y_vel))
+        )
+        .collect())
+}
+
+// Launch velocities that could possibly reach the target area, along each axis. None if there are
+// infinitely many that hit.
+fn velocity_bounds(target_area: &TargetArea) -> Option<(RangeInclusive<i32>, RangeInclusive<i32>)> {
+    // Any x velocity beyond the far edge of the target area would skip past it in a single step
+    let x_vels = target_area.min_x.min(0)..=target_area.max_x.max(0);
+
+    // Launching downwards faster than the distance to the bottom of the target area skips past it
+    // in one step. Launching upwards, the probe passes back down through the same heights, so it
+    // must either meet the top of the target area within one step of leaving or of returning to
+    // the origin, where it's then moving one faster than its launch velocity.
+    let mut max_y_vel = target_area.max_y.max(-target_area.min_y - 1);
+
+    // Except that an upwards launch comes back to exactly the launch height after twice its velocity
+    // plus one steps, so a target area level with the launcher is hit by any launch for which the
+    // probe is still horizontally within it by then
+    if target_area.min_y <= 0 && target_area.max_y >= 0 {
+        let mut last_steps = x_vels.clone().filter_map(|x_vel| x_step_range(x_vel, target_area));
+        // A probe that comes to rest above or below the target area can rise as high as it likes
+        let latest = last_steps.try_fold(0, |latest, steps| steps.last.map(|last| latest.max(last)))?;
+        max_y_vel = max_y_vel.max(((latest - 1) / 2) as i32);
+    }
+
+    Some((x_vels, target_area.min_y.min(0)..=max_y_vel))
+}
+
+// Inclusive range of steps, where a missing last step means the range never ends
+#[derive(Debug, Clone, Copy)]
+struct StepRange {
+    first: i64,
+    last: Option<i64>
+}
+
+impl StepRange {
+    fn overlaps(&self, other: &Self) -> bool {
+        self.last.iter().all(|&last| other.first <= last)
+        && other.last.iter().all(|&last| self.first <= last)
+    }
+}
+
+// Position after a number of steps for a starting velocity that drops by one each step, ignoring
+// drag bringing horizontal motion to a stop
+fn position_after(vel: i64, steps: i64) -> i64 {
+    steps * vel - steps * (steps - 1) / 2
+}
+
+// The position after n steps is the quadratic (2v + 1)n/2 - n²/2, and so reaches a coordinate c at
+// the real roots of n² - (2v + 1)n + 2c = 0, if there are any
+fn crossing_steps(vel: i64, coord: i64) -> Option<(f64, f64)> {
+    let linear = (2 * vel + 1) as f64;
+    let discriminant = linear * linear - 8.0 * coord as f64;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    Some(((linear - root) / 2.0, (linear + root) / 2.0))
+}
+
+// The steps, from the first onwards, at which the position is at or beyond a coordinate. The
+// position rises to a peak then falls, so these always form a single range between the rounded
+// roots. The floating point roots may be slightly out, so each end is nudged to the exact step,
+// never past the peak.
+fn steps_reaching(vel: i64, coord: i64) -> Option<(i64, i64)> {
+    let (low_root, high_root) = crossing_steps(vel, coord)?;
+    let reached = |step: i64| position_after(vel, step) >= coord;
+    let peak = vel.max(1);
+    if !reached(peak) {
+        return None;
+    }
+
+    let mut first = (low_root.ceil() as i64).clamp(1, peak);
+    while first > 1 && reached(first - 1) {
+        first -= 1;
+    }
+    while !reached(first) {
+        first += 1;
+    }
+
+    let mut last = (high_root.floor() as i64).max(peak);
+    while reached(last + 1) {
+        last += 1;
+    }
+    while !reached(last) {
+        last -= 1;
+    }
+    Some((first, last))
+}
+
+fn x_step_range(x_vel: i32, target_area: &TargetArea) -> Option<StepRange> {
+    // Horizontal motion is symmetric, so a leftwards launch is a rightwards one at a mirrored area
+    if x_vel < 0 {
+        return x_step_range(-x_vel, &target_area.mirrored());
+    }
+
+    let (vel, min_x, max_x) = (x_vel as i64, target_area.min_x as i64, target_area.max_x as i64);
+    // Drag stops the probe after as many steps as its initial velocity, and until then it moves
+    // like it would without drag
+    let resting_x = position_after(vel, vel);
+    if resting_x < min_x {
+        return None;
+    }
+    let (first, _) = steps_reaching(vel, min_x)?;
+
+    // A probe that comes to rest within the target area stays there forever
+    let last = if resting_x <= max_x {
+        None
+    } else {
+        Some(steps_reaching(vel, max_x + 1)?.0 - 1)
+    };
+
+    match last {
+        Some(last) if last < first => None,
+        _ => Some(StepRange {first, last})
+    }
+}
+
+fn y_step_ranges(y_vel: i32, target_area: &TargetArea) -> Vec<StepRange> {
+    let (vel, min_y, max_y) = (y_vel as i64, target_area.min_y as i64, target_area.max_y as i64);
+    let (first, last) = match steps_reaching(vel, min_y) {
+        Some(steps) => steps,
+        None => return vec!()
+    };
+
+    // Take out the steps where the probe is above the target area, which may split the range into
+    // a part on the way up and a part on the way down
+    match steps_reaching(vel, max_y + 1) {
+        None => vec!(StepRange {first, last: Some(last)}),
+        Some((above_first, above_last)) => [(first, above_first - 1), (above_last + 1, last)].iter()
+            .filter(|(first, last)| first <= last)
+            .map(|&(first, last)| StepRange {first, last: Some(last)})
+            .collect()
     }
-    success_count
 }
 
-fn probe_ends_within_area(mut probe: Probe, target_area: &TargetArea) -> bool {
+// Positions of the probe after each step until it either reaches the target area or is sure to
+// miss it, and whether it was a hit
+fn trajectory(mut probe: Probe, target_area: &TargetArea) -> (Vec<(i64, i64)>, bool) {
+    let mut positions = Vec::<(i64, i64)>::new();
     while !end_simulation(&probe, target_area) {
         update_probe(&mut probe);
+        positions.push((probe.x_pos, probe.y_pos));
         if target_area.contains(&probe) {
-            return true;
+            return (positions, true);
+        }
+    }
+    (positions, false)
+}
+
+// Draw the trajectory in the style of the puzzle text, with the launch point as S, the probe's
+// positions as # and the target area as T. Up is up, so rows run from the highest position down.
+// When the plot is too large, each character stands for a block of positions and shows the most
+// important thing within it.
+fn plot_trajectory(probe: Probe, target_area: &TargetArea) {
+    let (positions, hit) = trajectory(probe, target_area);
+
+    let (target_min_x, target_max_x) = (target_area.min_x as i64, target_area.max_x as i64);
+    let (target_min_y, target_max_y) = (target_area.min_y as i64, target_area.max_y as i64);
+    let xs = positions.iter().map(|pos| pos.0).chain([0, target_min_x, target_max_x]);
+    let ys = positions.iter().map(|pos| pos.1).chain([0, target_min_y, target_max_y]);
+    let (min_x, max_x) = xs.minmax().into_option().unwrap();
+    let (min_y, max_y) = ys.minmax().into_option().unwrap();
+
+    // Positions per character along each axis, rounded up so the plot always fits
+    let x_scale = (max_x - min_x + PLOT_WIDTH) / PLOT_WIDTH;
+    let y_scale = (max_y - min_y + PLOT_HEIGHT) / PLOT_HEIGHT;
+    let column = |x: i64| ((x - min_x) / x_scale) as usize;
+    let row = |y: i64| ((max_y - y) / y_scale) as usize;
+
+    let mut grid = vec![vec!['.'; column(max_x) + 1]; row(min_y) + 1];
+    for cells in &mut grid[row(target_max_y)..=row(target_min_y)] {
+        for cell in &mut cells[column(target_min_x)..=column(target_max_x)] {
+            *cell = 'T';
         }
     }
-    false
+    for &(x, y) in &positions {
+        grid[row(y)][column(x)] = '#';
+    }
+    grid[row(0)][column(0)] = 'S';
+
+    if x_scale > 1 || y_scale > 1 {
+        println!("Each character covers {} by {} positions", x_scale, y_scale);
+    }
+    for cells in grid {
+        println!("{}", cells.iter().collect::<String>());
+    }
+    if hit {
+        println!("{},{} hits the target area after {} steps", probe.x_vel, probe.y_vel, positions.len());
+    } else {
+        println!("{},{} misses the target area", probe.x_vel, probe.y_vel);
+    }
 }
 
 fn end_simulation(probe: &Probe, target_area: &TargetArea) -> bool {
-    // If the probe is now further than the target area
-    probe.x_pos > target_area.max_x
+    // If the probe is now past the target area in its direction of travel, or has stopped
+    // moving horizontally without being in line with it
+    let past_horizontally = match probe.x_vel.signum() {
+        1 => probe.x_pos > target_area.max_x as i64,
+        -1 => probe.x_pos < target_area.min_x as i64,
+        _ => probe.x_pos < target_area.min_x as i64 || probe.x_pos > target_area.max_x as i64
+    };
+    past_horizontally
     // If the probe is below the target area and descending
-    || (probe.y_pos < target_area.min_y && probe.y_vel < 0)
+    || (probe.y_pos < target_area.min_y as i64 && probe.y_vel < 0)
 }
 
 fn update_probe(probe: &mut Probe) {
     probe.x_pos += probe.x_vel;
     probe.y_pos += probe.y_vel;
     probe.y_vel -= 1;
-    if probe.x_vel > 0 {
-        probe.x_vel -= 1;
-    }
+    // Drag slows horizontal motion in either direction until it stops
+    probe.x_vel -= probe.x_vel.signum();
 }
 
 fn height_for_initial_y_vel(y_vel: i32) -> i32 {
     // Total height with motion rules used is triangular quadratic relative to
-    // the initial y velocity
-    (y_vel * (y_vel + 1)) / 2
+    // the initial y velocity. A probe launched level or downwards never rises
+    // above the launch point.
+    if y_vel > 0 {(y_vel * (y_vel + 1)) / 2} else {0}
 }
 
 fn parse_target_area(line_in: &str) -> TargetArea {
@@ -92,12 +304,14 @@ fn parse_target_area(line_in: &str) -> TargetArea {
     target_area
 }
 
+// Positions are kept wider than the velocities they're launched with, as even a launch that fits
+// in 32 bits can climb far beyond them
 #[derive(Debug, Clone, Copy)]
 struct Probe {
-    x_pos: i32,
-    y_pos: i32,
-    x_vel: i32,
-    y_vel: i32
+    x_pos: i64,
+    y_pos: i64,
+    x_vel: i64,
+    y_vel: i64
 }
 
 #[derive(Debug)]
@@ -110,9 +324,119 @@ struct TargetArea {
 
 impl TargetArea {
     fn contains(&self, probe: &Probe) -> bool {
-        probe.x_pos >= self.min_x
-        && probe.x_pos <= self.max_x
-        && probe.y_pos >= self.min_y
-        && probe.y_pos <= self.max_y
+        probe.x_pos >= self.min_x as i64
+        && probe.x_pos <= self.max_x as i64
+        && probe.y_pos >= self.min_y as i64
+        && probe.y_pos <= self.max_y as i64
+    }
+
+    // The same area reflected to the other side of the launcher horizontally
+    fn mirrored(&self) -> Self {
+        Self {min_x: -self.max_x, max_x: -self.min_x, min_y: self.min_y, max_y: self.max_y}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::Rng;
+    use itertools::iproduct;
+
+    // The original brute force search, simulating each probe step by step. Velocities are searched
+    // over a box that must be chosen wide enough to hold every hit, rather than trusting the bounds
+    // that step ranges are found within.
+    fn simulated_hitting_velocities(target_area: &TargetArea, extent: i32) -> Vec<(i32, i32)> {
+        iproduct!(-extent..=extent, -extent..=extent)
+            .filter(|&(x_vel, y_vel)| probe_ends_within_area(
+                Probe {x_pos: 0, y_pos: 0, x_vel: x_vel as i64, y_vel: y_vel as i64},
+                target_area
+            ))
+            .collect()
+    }
+
+    fn probe_ends_within_area(mut probe: Probe, target_area: &TargetArea) -> bool {
+        while !end_simulation(&probe, target_area) {
+            update_probe(&mut probe);
+            if target_area.contains(&probe) {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn random_target_area(rng: &mut Rng, extent: i32) -> TargetArea {
+        let (min_x, max_x) = (rng.between(-extent, extent), rng.between(-extent, extent));
+        let (min_y, max_y) = (rng.between(-extent, extent), rng.between(-extent, extent));
+        TargetArea {min_x: min_x.min(max_x), max_x: min_x.max(max_x), min_y: min_y.min(max_y), max_y: min_y.max(max_y)}
+    }
+
+    fn sorted_hitting_velocities(target_area: &TargetArea) -> Option<Vec<(i32, i32)>> {
+        hitting_velocities(target_area).map(|velocities| velocities.into_iter().sorted().collect())
+    }
+
+    // Step ranges must find exactly the velocities that simulation does for the example in the
+    // puzzle text, where no hit can be faster than the target area is far away
+    #[test]
+    fn example_matches_simulation() {
+        let example = parse_target_area("target area: x=20..30, y=-10..-5");
+        let velocities = sorted_hitting_velocities(&example).unwrap();
+        assert_eq!(velocities.len(), 112);
+        assert_eq!(velocities, simulated_hitting_velocities(&example, 120));
     }
-}
\ No newline at end of file
+
+    // Target areas anywhere around the launcher, including level with and above it. Hits are all
+    // well within a box four times the size of the areas, even for those level with the launcher, as
+    // a probe that doesn't come to rest within the area is only ever in line with it for a few steps.
+    #[test]
+    fn random_target_areas_match_simulation() {
+        let mut rng = Rng::new(17);
+        let extent = 12;
+        for _ in 0..60 {
+            let target_area = random_target_area(&mut rng, extent);
+            match sorted_hitting_velocities(&target_area) {
+                Some(velocities) => assert_eq!(
+                    velocities, simulated_hitting_velocities(&target_area, 4 * extent),
+                    "Velocities differ for {:?}", target_area
+                ),
+                // Infinitely many hits means that launches far higher than the box still hit
+                None => assert!(
+                    (-extent..=extent).any(|x_vel| probe_ends_within_area(
+                        Probe {x_pos: 0, y_pos: 0, x_vel: x_vel as i64, y_vel: 1000},
+                        &target_area
+                    )),
+                    "No high launch hits {:?}", target_area
+                )
+            }
+        }
+    }
+
+    // Probes only come to rest at triangular numbers, so an area level with the launcher and strictly
+    // between two of them is only hit by finitely many launches, for which the upwards velocity is
+    // bounded by how long a probe passing through stays in line with it
+    #[test]
+    fn level_target_areas_between_resting_points_match_simulation() {
+        let mut rng = Rng::new(39);
+        for _ in 0..30 {
+            let resting = 2 + rng.below(3) as i32;
+            let (near, far) = (resting * (resting + 1) / 2 + 1, (resting + 1) * (resting + 2) / 2 - 1);
+            let min_x = rng.between(near, far);
+            let max_x = rng.between(min_x, far);
+            let target_area = TargetArea {min_x, max_x, min_y: rng.between(-12, 0), max_y: rng.between(0, 12)};
+            let target_area = if rng.below(2) == 0 {target_area} else {target_area.mirrored()};
+
+            let velocities = sorted_hitting_velocities(&target_area).expect("Only finitely many launches should hit");
+            assert_eq!(velocities, simulated_hitting_velocities(&target_area, 60), "Velocities differ for {:?}", target_area);
+        }
+    }
+
+    // A probe launched at 2 comes to rest at 3 within this area, and then falls back down through
+    // it however high it was launched
+    #[test]
+    fn resting_above_level_area_hits_infinitely() {
+        let target_area = TargetArea {min_x: 2, max_x: 5, min_y: -3, max_y: 3};
+        assert!(hitting_velocities(&target_area).is_none());
+        for y_vel in [10, 100, 1000] {
+            assert!(probe_ends_within_area(Probe {x_pos: 0, y_pos: 0, x_vel: 2, y_vel}, &target_area));
+        }
+    }
+}
@@ -1,38 +1,115 @@
-use std::{fmt, ptr::eq};
+use std::{fmt, ptr::eq, str::FromStr, time::Instant};
 use itertools::iproduct;
 
-use crate::utils::read_string_lines;
+use crate::utils::{flag_value, has_flag, read_string_lines};
 
 pub fn solution18 () {
     let input = read_input("src/data/solution18.txt");
-    println!("{}", solution18a(&input));
-    println!("{}", solution18b(&input));
+    let rules = Rules::from_flags();
+    // Solutions work on the flat representation, which is much faster to reduce
+    let flat_input = input.iter()
+        .map(|number| FlatNumber::from_pair(number))
+        .collect::<Vec<FlatNumber>>();
+    println!("{}", solution18a(&flat_input, &rules));
+    println!("{}", solution18b(&flat_input, &rules));
+
+    if has_flag("--trace") {
+        trace_sum(&input, &rules);
+    }
+
+    if has_flag("--check") {
+        check_flat_matches_tree(&input, &flat_input, &rules);
+    }
 }
 
-fn solution18a(input: &[SnailfishNumber]) -> u32 {
+fn solution18a(input: &[FlatNumber], rules: &Rules) -> u32 {
     // Just add all of the Snailfish numbers together and determine magnitude
     input.iter()
         .cloned()
-        .reduce(add_numbers)
+        .reduce(|left, right| left.add(&right, rules))
         .expect("Input data is empty of valid Snailfish numbers")
-        .magnitude()
+        .magnitude(rules)
 }
 
-fn solution18b(input: &[SnailfishNumber]) -> u32 {
+fn solution18b(input: &[FlatNumber], rules: &Rules) -> u32 {
     // Cartesian product to find each pair of numbers
     iproduct!(input, input)
         // Numbers must be different from each other
         .filter(|(left, right)| !eq(*left, *right))    
-        // Find magnitude of their sum
-        .map(|(left, right)|
-            add_numbers(left.clone(), right.clone()).magnitude()
-        )
+        // Find magnitude of their sum, without needing to clone either operand
+        .map(|(left, right)| left.add(right, rules).magnitude(rules))
         // We are interested in only the biggest result
         .max()
         .expect("Input data is empty of valid Snailfish numbers")
 }
 
-fn add_numbers(left: SnailfishNumber, right: SnailfishNumber) -> SnailfishNumber {
+// Repeat both parts with the original tree representation, reporting any disagreement
+fn check_flat_matches_tree(input: &[SnailfishNumber], flat_input: &[FlatNumber], rules: &Rules) {
+    let tree_sum = input.iter()
+        .cloned()
+        .reduce(|left, right| add_numbers(left, right, rules))
+        .expect("Input data is empty of valid Snailfish numbers");
+    let flat_sum = flat_input.iter()
+        .cloned()
+        .reduce(|left, right| left.add(&right, rules))
+        .expect("Input data is empty of valid Snailfish numbers");
+    if FlatNumber::from_pair(&tree_sum) != flat_sum || tree_sum.magnitude(rules) != flat_sum.magnitude(rules) {
+        println!("Mismatch summing all numbers: tree {:?}, flat {:?}", tree_sum, flat_sum);
+    }
+
+    let mut mismatches = 0;
+    for ((left, right), (flat_left, flat_right)) in iproduct!(input, input).zip(iproduct!(flat_input, flat_input)) {
+        if eq(left, right) {
+            continue;
+        }
+        let tree_result = add_numbers(left.clone(), right.clone(), rules);
+        let flat_result = flat_left.add(flat_right, rules);
+        if FlatNumber::from_pair(&tree_result) != flat_result || tree_result.magnitude(rules) != flat_result.magnitude(rules) {
+            mismatches += 1;
+        }
+    }
+    println!("Flat and tree representations disagreed on {} pair sums", mismatches);
+
+    // The flat representation is there for speed, so compare how long part B takes in each
+    let flat_start = Instant::now();
+    let flat_largest = solution18b(flat_input, rules);
+    let flat_time = flat_start.elapsed();
+    let tree_start = Instant::now();
+    let tree_largest = iproduct!(input, input)
+        .filter(|(left, right)| !eq(*left, *right))
+        .map(|(left, right)| add_numbers(left.clone(), right.clone(), rules).magnitude(rules))
+        .max()
+        .expect("Input data is empty of valid Snailfish numbers");
+    let tree_time = tree_start.elapsed();
+    println!(
+        "Part B gave {} in {:.1?} with the flat representation and {} in {:.1?} with the tree",
+        flat_largest, flat_time, tree_largest, tree_time
+    );
+}
+
+// Repeat the part A sum with the tree representation, printing every reduction action on the way
+fn trace_sum(input: &[SnailfishNumber], rules: &Rules) {
+    input.iter()
+        .cloned()
+        .reduce(|left, right| {
+            println!("  {}\n+ {}", left, right);
+            let mut trace = Vec::<TraceStep>::new();
+            let combined = reduce_sum(left, right, rules, Some(&mut trace));
+            for step in trace {
+                println!("{}", step);
+            }
+            println!("= {}\n", combined);
+            combined
+        })
+        .expect("Input data is empty of valid Snailfish numbers");
+}
+
+fn add_numbers(left: SnailfishNumber, right: SnailfishNumber, rules: &Rules) -> SnailfishNumber {
+    reduce_sum(left, right, rules, None)
+}
+
+// Adds the numbers together and reduces the result, recording each action taken if given a trace
+fn reduce_sum(left: SnailfishNumber, right: SnailfishNumber, rules: &Rules, mut trace: Option<&mut Vec<TraceStep>>) -> SnailfishNumber {
     let mut combined = Box::new(
         Pair {
             left: Node::Pair(left),
@@ -40,28 +117,108 @@ fn add_numbers(left: SnailfishNumber, right: SnailfishNumber) -> SnailfishNumber
         }
     );
 
+    if let Some(trace) = trace.as_mut() {
+        trace.push(TraceStep {action: ReductionAction::Addition, result: combined.clone()});
+    }
+
     // Check for explodes and splits until none are required
-    while combined.try_explode_children(1).exploded || combined.try_split_children() { }
+    loop {
+        let action = if let Some(pair) = combined.try_explode_children(1, rules).exploded {
+            ReductionAction::Explode(pair)
+        } else if let Some(value) = combined.try_split_children(rules) {
+            ReductionAction::Split(value)
+        } else {
+            break;
+        };
+
+        if let Some(trace) = trace.as_mut() {
+            trace.push(TraceStep {action, result: combined.clone()});
+        }
+    }
 
     combined
 }
 
-const SPLIT_LIMIT: u32 = 10;
-const OUTER_PAIR_LIMIT: u32 = 4;
+// A single action taken while reducing a sum, and the whole number straight after it
+struct TraceStep {
+    action: ReductionAction,
+    result: SnailfishNumber
+}
+
+enum ReductionAction {
+    Addition,
+    // The pair that exploded
+    Explode(Pair),
+    // The regular number that split
+    Split(u32)
+}
+
+// Laid out as in the worked example of the puzzle text
+impl fmt::Display for TraceStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match &self.action {
+            ReductionAction::Addition => String::from("after addition:"),
+            ReductionAction::Explode(pair) => format!("after explode {}:", pair),
+            ReductionAction::Split(value) => format!("after split {}:", value)
+        };
+        write!(f, "{:<26}{}", action, self.result)
+    }
+}
+
+// Settings that govern how numbers reduce and how their magnitude is found. The puzzle's own
+// settings are the defaults, but any of them can be overridden from the command line.
+#[derive(Debug)]
+struct Rules {
+    // Regular numbers of at least this value split
+    split_limit: u32,
+    // Pairs nested inside at least this many pairs explode
+    outer_pair_limit: u32,
+    // Magnitude multipliers for the left and right halves of a pair
+    left_weight: u32,
+    right_weight: u32
+}
+
+const PUZZLE_RULES: Rules = Rules {
+    split_limit: 10,
+    outer_pair_limit: 4,
+    left_weight: 3,
+    right_weight: 2
+};
+
+impl Rules {
+    fn from_flags() -> Self {
+        let setting = |flag: &str, default: u32| flag_value(flag)
+            .map(|value| value.parse::<u32>().unwrap_or_else(|_| panic!("Invalid value for {}", flag)))
+            .unwrap_or(default);
+
+        let rules = Self {
+            split_limit: setting("--split-limit", PUZZLE_RULES.split_limit),
+            outer_pair_limit: setting("--depth-limit", PUZZLE_RULES.outer_pair_limit),
+            left_weight: setting("--left-weight", PUZZLE_RULES.left_weight),
+            right_weight: setting("--right-weight", PUZZLE_RULES.right_weight)
+        };
+
+        // A value of one would split into a pair containing one again, and the outermost pair has
+        // no parent to explode into
+        assert!(rules.split_limit >= 2, "Split limit must be at least 2");
+        assert!(rules.outer_pair_limit >= 1, "Depth limit must be at least 1");
+        rules
+    }
+}
 
 trait Magnitude { 
-    fn magnitude(&self) -> u32;
+    fn magnitude(&self, rules: &Rules) -> u32;
 }
 
 type SnailfishNumber = Box<Pair>;
 
 impl Magnitude for SnailfishNumber {
-    fn magnitude(&self) -> u32 {
-        self.as_ref().magnitude()
+    fn magnitude(&self, rules: &Rules) -> u32 {
+        self.as_ref().magnitude(rules)
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 struct Pair {
     left: Node,
     right: Node,
@@ -73,13 +230,20 @@ impl fmt::Debug for Pair {
     }
 }
 
+// Written in the same bracket notation as the puzzle input
+impl fmt::Display for Pair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{},{}]", self.left, self.right)
+    }
+}
+
 impl Magnitude for Pair {
-    fn magnitude(&self) -> u32 {
-        self.left.magnitude() * 3 + self.right.magnitude() * 2
+    fn magnitude(&self, rules: &Rules) -> u32 {
+        self.left.magnitude(rules) * rules.left_weight + self.right.magnitude(rules) * rules.right_weight
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 enum Node {
     Pair(Box<Pair>),
     Value(u32)
@@ -94,17 +258,27 @@ impl fmt::Debug for Node {
     }
 }
 
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            Node::Value(value) => write!(f, "{}", value),
+            Node::Pair(pair) => pair.fmt(f)
+        }
+    }
+}
+
 impl Magnitude for Node {
-    fn magnitude(&self) -> u32 {
+    fn magnitude(&self, rules: &Rules) -> u32 {
         match self {
-            Node::Pair(pair) => pair.magnitude(), 
+            Node::Pair(pair) => pair.magnitude(rules), 
             Node::Value(value) => *value
         }
     }
 }
 
 struct ExplodeResult {
-    exploded: bool,
+    // The pair that exploded, if any
+    exploded: Option<Pair>,
     carry_value: Option<ExplodeCarryValue>,
 }
 
@@ -117,6 +291,10 @@ struct ExplodeCarryValue {
 enum PairHalf {Left, Right}
 
 impl Pair {
+    fn holds_values(&self) -> bool {
+        matches!((&self.left, &self.right), (Node::Value(_), Node::Value(_)))
+    }
+
     fn create_carry_values(&self, split_half: PairHalf) -> (ExplodeCarryValue, ExplodeCarryValue) {
         let left_carry_value = ExplodeCarryValue{
             pair_half: PairHalf::Left,
@@ -134,7 +312,7 @@ impl Pair {
         }
     }
 
-    fn try_explode_children(&mut self, outer_pairs: u32) -> ExplodeResult {
+    fn try_explode_children(&mut self, outer_pairs: u32, rules: &Rules) -> ExplodeResult {
         // Check left child before right
         for check_half in vec!(PairHalf::Left, PairHalf::Right) {
             let (child, other_child) = if check_half == PairHalf::Left {
@@ -144,20 +322,22 @@ impl Pair {
             };
 
             // Only children that are pairs are in danger of exploding (directly or recursively)
-            // NOTE: There is an assumption that an exploding pair will have values as both children, as regular
+            // NOTE: Under the puzzle rules an exploding pair will always have values as both children, as regular
             // exploding after each add should not result in a pair reaching the depth limit while having further
-            // pairs beneath them
+            // pairs beneath them. With a lower depth limit this no longer holds, so only pairs of two values
+            // explode and any deeper pairs are searched instead.
             if let Node::Pair(pair) = child {
                 // Explode at outer pair limit
-                if outer_pairs >= OUTER_PAIR_LIMIT {
+                if outer_pairs >= rules.outer_pair_limit && pair.holds_values() {
                     // The exploded child is broken into two carried values and is set to zero
                     let (propagate_carry, other_child_accepts) = pair.create_carry_values(check_half);
+                    let exploded = (**pair).clone();
                     *child = Node::Value(0);
                     // One of the split values is accepted by the other child
                     other_child.accept_carry_value(&other_child_accepts);
                     // The other split value must propagate up the tree and then down again
                     return ExplodeResult {
-                        exploded: true,
+                        exploded: Some(exploded),
                         carry_value: Some(propagate_carry)
                     };
                 } else {
@@ -165,8 +345,8 @@ impl Pair {
                     // explosions. An explosion may also come with a left- or right- fragment that needs to
                     // be shifted left or right along the tree. In practical terms this involves moving the
                     // fragment up the tree and then down again.
-                    let mut explode_attempt = pair.try_explode_children(outer_pairs+1);
-                    if explode_attempt.exploded {
+                    let mut explode_attempt = pair.try_explode_children(outer_pairs+1, rules);
+                    if explode_attempt.exploded.is_some() {
                         if let Some(carry_value) = &explode_attempt.carry_value {
                             // See if the other child is in the right direction to accept the propagated
                             // value from the explosion
@@ -182,25 +362,27 @@ impl Pair {
         }
         // If no early returns then no explodes are required
         ExplodeResult{
-            exploded: false,
+            exploded: None,
             carry_value: None
         }                
     }
 
-    fn try_split_children(&mut self) -> bool {
+    // Returns the regular number that was split, if any
+    fn try_split_children(&mut self, rules: &Rules) -> Option<u32> {
         for child in [&mut self.left, &mut self.right] {
-            if match child {
-                Node::Pair(pair) => pair.try_split_children(),
-                Node::Value(value) if (*value >= SPLIT_LIMIT) => {
+            if let Some(split_value) = match child {
+                Node::Pair(pair) => pair.try_split_children(rules),
+                Node::Value(value) if (*value >= rules.split_limit) => {
+                    let split_value = *value;
                     child.split();
-                    true
+                    Some(split_value)
                 },
-                _ => false
+                _ => None
             } {
-                return true;
+                return Some(split_value);
             }
         }
-        false // No splits required
+        None // No splits required
     }
 }
 
@@ -242,47 +424,307 @@ impl Node {
     }
 }
 
+// Flat representation of a Snailfish number, as its regular values from left to right along with
+// the number of pairs enclosing each of them. Explodes and splits become linear scans over the
+// values rather than recursive searches through a tree of boxed pairs.
+#[derive(Clone, Debug, PartialEq)]
+struct FlatNumber(Vec<FlatValue>);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct FlatValue {
+    value: u32,
+    depth: u32
+}
+
+impl FlatNumber {
+    fn from_pair(pair: &Pair) -> Self {
+        let mut values = Vec::<FlatValue>::new();
+        Self::flatten_node(&pair.left, 1, &mut values);
+        Self::flatten_node(&pair.right, 1, &mut values);
+        Self(values)
+    }
+
+    fn flatten_node(node: &Node, depth: u32, values: &mut Vec<FlatValue>) {
+        match node {
+            Node::Value(value) => values.push(FlatValue {value: *value, depth}),
+            Node::Pair(pair) => {
+                Self::flatten_node(&pair.left, depth + 1, values);
+                Self::flatten_node(&pair.right, depth + 1, values);
+            }
+        }
+    }
+
+    fn add(&self, other: &Self, rules: &Rules) -> Self {
+        // Both operands sink one level below the new outer pair
+        let mut combined = Self(
+            self.0.iter()
+                .chain(other.0.iter())
+                .map(|entry| FlatValue {value: entry.value, depth: entry.depth + 1})
+                .collect()
+        );
+
+        // Check for explodes and splits until none are required
+        while combined.try_explode(rules) || combined.try_split(rules) { }
+
+        combined
+    }
+
+    fn try_explode(&mut self, rules: &Rules) -> bool {
+        let idx = match self.find_exploding_pair(rules) {
+            Some(idx) => idx,
+            None => return false
+        };
+        let (left, right) = (self.0[idx], self.0[idx+1]);
+
+        // Carry values go to the nearest values on either side, if there are any
+        if idx > 0 {
+            self.0[idx-1].value += left.value;
+        }
+        if let Some(next) = self.0.get_mut(idx+2) {
+            next.value += right.value;
+        }
+
+        // The pair is replaced by zero in the position of its parent
+        self.0[idx] = FlatValue {value: 0, depth: left.depth - 1};
+        self.0.remove(idx+1);
+        true
+    }
+
+    // Index of the left value of the leftmost pair of two values that is nested too deeply. The first
+    // value past the depth limit is always a left half, as anything to its left in the same pair
+    // would be nested at least as deeply. Under the puzzle rules numbers never get more than one
+    // level too deep, so its right half is the next value along and this is the pair to explode.
+    fn find_exploding_pair(&self, rules: &Rules) -> Option<usize> {
+        let idx = self.0.iter().position(|entry| entry.depth > rules.outer_pair_limit)?;
+        if self.0[idx+1].depth == self.0[idx].depth {
+            return Some(idx);
+        }
+        self.find_nested_exploding_pair(idx, rules)
+    }
+
+    // Lower limits can leave pairs of pairs nested too deeply, so the leftmost pair of two values
+    // must be searched for. Two neighbouring values at the same depth are either a pair, or a right
+    // half followed by a left half. A right half like that has a pair for its left sibling, which
+    // holds a pair of two values further left, so the first two neighbours that match are a pair.
+    fn find_nested_exploding_pair(&self, first_deep: usize, rules: &Rules) -> Option<usize> {
+        self.0[first_deep..].windows(2)
+            .position(|pair| pair[0].depth > rules.outer_pair_limit && pair[1].depth == pair[0].depth)
+            .map(|idx| first_deep + idx)
+    }
+
+    fn try_split(&mut self, rules: &Rules) -> bool {
+        let idx = match self.0.iter().position(|entry| entry.value >= rules.split_limit) {
+            Some(idx) => idx,
+            None => return false
+        };
+        let FlatValue {value, depth} = self.0[idx];
+
+        let left_val = value / 2; // Left half rounds down (integer division)
+        self.0[idx] = FlatValue {value: left_val, depth: depth + 1};
+        self.0.insert(idx+1, FlatValue {value: value - left_val, depth: depth + 1});
+        true
+    }
+
+    fn magnitude(&self, rules: &Rules) -> u32 {
+        // Values are collapsed onto a stack as they are read. Whenever the two values on top share a
+        // depth they must be the two halves of the same pair, and can be replaced by its magnitude.
+        let mut stack = Vec::<FlatValue>::new();
+        for &entry in &self.0 {
+            stack.push(entry);
+            while stack.len() >= 2 && stack[stack.len()-1].depth == stack[stack.len()-2].depth {
+                let right = stack.pop().unwrap();
+                let left = stack.pop().unwrap();
+                stack.push(FlatValue {
+                    value: left.value * rules.left_weight + right.value * rules.right_weight,
+                    depth: left.depth - 1
+                });
+            }
+        }
+        stack.first().expect("Snailfish number has no values").value
+    }
+}
+
 fn read_input(path: &str) -> Vec<SnailfishNumber> {
     read_string_lines(path).iter()
-        .map(String::as_str)
-        .map(parse_snailfish_number)
+        .map(|line| line.parse::<Pair>()
+            .unwrap_or_else(|error| panic!("Invalid Snailfish number {}: {}", line, error))
+        )
+        .map(Box::new)
         .collect()
 }
 
-fn parse_snailfish_number(num_ser: &str) -> SnailfishNumber {
-    Box::new(
-        parse_pair(&num_ser[1..num_ser.len()-1])
-    )
+impl FromStr for Pair {
+    type Err = ParseError;
+
+    fn from_str(num_ser: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { chars: num_ser.chars().collect(), position: 0 };
+        let pair = parser.parse_pair()?;
+
+        // The outermost pair must account for the entire string
+        match parser.peek() {
+            Some(found) => Err(parser.error(ParseErrorReason::Unexpected {found, expected: "end of number"})),
+            None => Ok(pair)
+        }
+    }
 }
 
-fn parse_node(node_ser: &str) -> Node {
-    if !node_ser.starts_with('[') {
-        Node::Value(node_ser.parse::<u32>().expect("Invalid Snailfish number"))
-    } else {
-        Node::Pair(
-            Box::new(parse_pair(&node_ser[1..node_ser.len()-1]))
-        )
+#[derive(Debug)]
+struct ParseError {
+    // Character index into the string where the problem was found
+    position: usize,
+    reason: ParseErrorReason
+}
+
+#[derive(Debug)]
+enum ParseErrorReason {
+    // A character that can't appear at this point of a Snailfish number
+    Unexpected {found: char, expected: &'static str},
+    // The string finished while a pair was still open
+    UnexpectedEnd {expected: &'static str},
+    // Regular numbers are written without leading zeros, so each has a single serialisation
+    LeadingZero,
+    // A regular number too large to be represented
+    ValueTooLarge
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "character {}: ", self.position)?;
+        match &self.reason {
+            ParseErrorReason::Unexpected {found, expected} => write!(f, "found '{}' but expected {}", found, expected),
+            ParseErrorReason::UnexpectedEnd {expected} => write!(f, "number ended but expected {}", expected),
+            ParseErrorReason::LeadingZero => write!(f, "regular number has a leading zero"),
+            ParseErrorReason::ValueTooLarge => write!(f, "regular number is too large")
+        }
     }
 }
 
-fn parse_pair(pair_ser: &str) -> Pair {
-    let comma_pos = find_comma(pair_ser);
-    Pair{
-        left: parse_node(&pair_ser[..comma_pos]),
-        right: parse_node(&pair_ser[(comma_pos+1)..])
+// Recursive descent over the characters of a serialised Snailfish number
+struct Parser {
+    chars: Vec<char>,
+    position: usize
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn error(&self, reason: ParseErrorReason) -> ParseError {
+        ParseError {position: self.position, reason}
+    }
+
+    fn expect(&mut self, wanted: char, expected: &'static str) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(found) if found == wanted => {
+                self.position += 1;
+                Ok(())
+            },
+            Some(found) => Err(self.error(ParseErrorReason::Unexpected {found, expected})),
+            None => Err(self.error(ParseErrorReason::UnexpectedEnd {expected}))
+        }
+    }
+
+    fn parse_pair(&mut self) -> Result<Pair, ParseError> {
+        self.expect('[', "'['")?;
+        let left = self.parse_node()?;
+        self.expect(',', "','")?;
+        let right = self.parse_node()?;
+        self.expect(']', "']'")?;
+        Ok(Pair {left, right})
+    }
+
+    fn parse_node(&mut self) -> Result<Node, ParseError> {
+        match self.peek() {
+            Some('[') => Ok(Node::Pair(Box::new(self.parse_pair()?))),
+            Some(found) if found.is_ascii_digit() => {
+                let start = self.position;
+                while self.peek().is_some_and(|next| next.is_ascii_digit()) {
+                    self.position += 1;
+                }
+                if found == '0' && self.position - start > 1 {
+                    return Err(ParseError {position: start, reason: ParseErrorReason::LeadingZero});
+                }
+                self.chars[start..self.position].iter()
+                    .collect::<String>()
+                    .parse::<u32>()
+                    .map(Node::Value)
+                    .map_err(|_| ParseError {position: start, reason: ParseErrorReason::ValueTooLarge})
+            },
+            Some(found) => Err(self.error(ParseErrorReason::Unexpected {found, expected: "a pair or regular number"})),
+            None => Err(self.error(ParseErrorReason::UnexpectedEnd {expected: "a pair or regular number"}))
+        }
     }
 }
 
-fn find_comma(pair_ser: &str) -> usize {
-    let mut stack_count = 0;
-    for (idx, char) in pair_ser.chars().enumerate() {
-        match char {
-            ',' if stack_count == 0 => return idx,
-            '[' => stack_count += 1,
-            ']' if stack_count == 0 => panic!("Unexpected pair finish!"),
-            ']' => stack_count -= 1,
-            _ => ()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::Rng;
+
+    // Random well-formed numbers must survive being written out and read back in
+    #[test]
+    fn random_numbers_round_trip() {
+        let mut rng = Rng::new(18);
+        for _ in 0..10000 {
+            let number = random_pair(&mut rng, 1);
+            let serialised = number.to_string();
+            match serialised.parse::<Pair>() {
+                Ok(parsed) => assert!(parsed == number, "Round trip changed {} into {}", serialised, parsed),
+                Err(error) => panic!("Round trip failed to parse {}: {}", serialised, error)
+            }
+        }
+    }
+
+    // Randomly corrupted strings must either fail to parse or still round-trip
+    #[test]
+    fn corrupted_numbers_are_rejected_or_round_trip() {
+        let mut rng = Rng::new(18);
+        for _ in 0..10000 {
+            // Replace a single character with another from the number alphabet
+            let mut corrupted = random_pair(&mut rng, 1).to_string().chars().collect::<Vec<char>>();
+            let corrupt_idx = rng.below(corrupted.len() as u64) as usize;
+            corrupted[corrupt_idx] = ['[', ']', ',', '0', '7', 'x'][rng.below(6) as usize];
+            let corrupted = corrupted.into_iter().collect::<String>();
+            if let Ok(parsed) = corrupted.parse::<Pair>() {
+                assert_eq!(parsed.to_string(), corrupted, "Corrupted {} parsed but was written back differently", corrupted);
+            }
         }
     }
-    unreachable!("Failed to find comma in pair!");
-}
\ No newline at end of file
+
+    // The flat representation must reduce sums exactly as the tree does. Rules are randomised too, as
+    // depth limits below the nesting of the random numbers are what reach the nested pair search.
+    #[test]
+    fn flat_sums_match_tree_sums() {
+        let mut rng = Rng::new(29);
+        for _ in 0..5000 {
+            let rules = Rules {
+                split_limit: 2 + rng.below(19) as u32,
+                outer_pair_limit: 1 + rng.below(5) as u32,
+                left_weight: 1 + rng.below(3) as u32,
+                right_weight: 1 + rng.below(3) as u32
+            };
+            let (left, right) = (Box::new(random_pair(&mut rng, 1)), Box::new(random_pair(&mut rng, 1)));
+            let flat_sum = FlatNumber::from_pair(&left).add(&FlatNumber::from_pair(&right), &rules);
+            let tree_sum = add_numbers(left.clone(), right.clone(), &rules);
+            assert_eq!(FlatNumber::from_pair(&tree_sum), flat_sum, "Sums of {} and {} differ under {:?}", left, right, rules);
+            assert_eq!(tree_sum.magnitude(&rules), flat_sum.magnitude(&rules), "Magnitudes differ under {:?}", rules);
+        }
+    }
+
+    fn random_pair(rng: &mut Rng, depth: u32) -> Pair {
+        Pair {
+            left: random_node(rng, depth + 1),
+            right: random_node(rng, depth + 1)
+        }
+    }
+
+    fn random_node(rng: &mut Rng, depth: u32) -> Node {
+        // Deeper pairs are rarer, but numbers may still go beyond what reduction would allow
+        if depth <= 6 && rng.below(depth as u64 + 1) == 0 {
+            Node::Pair(Box::new(random_pair(rng, depth)))
+        } else {
+            Node::Value(rng.below(20) as u32)
+        }
+    }
+}
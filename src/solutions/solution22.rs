@@ -1,43 +1,224 @@
-use crate::utils::read_string_lines;
-use itertools::iproduct;
+use crate::random::Rng;
+use crate::utils::{flag_value, has_flag, read_string_lines};
+use itertools::{iproduct, Itertools};
 use regex::Regex;
 
-use std::{ops::RangeInclusive, cmp::{max, min}};
+use std::{fmt, time::{Duration, Instant}};
+
+mod export;
+mod geometry;
+use geometry::{CoordRange, Cube};
 
 pub fn solution22 () {
     let commands = parse_commands(&read_string_lines("src/data/solution22.txt"));
     println!("{}", solution22a(&commands));
     println!("{}", solution22b(&commands));
+
+    if has_flag("--inspect") {
+        inspect_reboot(&commands);
+    }
+
+    export_lit_regions(&commands);
+
+    if has_flag("--check") {
+        time_solvers(&commands, &mut Rng::new(22), 200);
+    }
 }
 
-fn solution22a(commands: &[Command]) -> usize {
-    let mut region = [[[false; 101]; 101]; 101];
-    let clip_to_region = |range: CoordRange| range.intersect_ranges(&CoordRange(-50..=50)).0;
-    let add_offset = |input: i32| (input + 50) as usize; 
+fn solution22a(commands: &[Command]) -> u128 {
+    compressed_lit_volume(commands, Some(&initialisation_region()))
+}
+
+fn solution22b(commands: &[Command]) -> u128 {    
+    if has_flag("--signed") {
+        signed_lit_volume(commands)
+    } else if has_flag("--compressed") {
+        compressed_lit_volume(commands, None)
+    } else {
+        splitting_lit_volume(commands)
+    }
+}
+
+fn initialisation_region() -> Cube {
+    Cube {
+        x_range: CoordRange(-50..=50),
+        y_range: CoordRange(-50..=50),
+        z_range: CoordRange(-50..=50)
+    }
+}
+
+// Coordinate compression. Only the coordinates where a command region starts or stops matter, so
+// space is cut into slabs between consecutive x boundaries, and each slab into cells between the
+// y and z boundaries of the commands crossing it. Every cube within a cell ends up in the same state,
+// so each cell needs a single flag however large it is. Commands can be clipped to given bounds.
+fn compressed_lit_volume(commands: &[Command], bounds: Option<&Cube>) -> u128 {
+    let clipped_commands = commands.iter()
+        .map(|command| Command {
+            instruction: command.instruction.clone(),
+            region: match bounds {
+                Some(bounds) => command.region.intersection(bounds),
+                None => command.region.clone()
+            }
+        })
+        .filter(|command| !command.region.is_empty())
+        .collect::<Vec<Command>>();
+
+    let x_bounds = range_boundaries(clipped_commands.iter().map(|command| &command.region.x_range));
+    let mut volume = 0u128;
+
+    for slab in x_bounds.windows(2) {
+        // Boundaries include every command start and end, so a command either spans the whole slab or misses it
+        let slab_commands = clipped_commands.iter()
+            .filter(|command| *command.region.x_range.0.start() as i64 <= slab[0] && slab[0] <= *command.region.x_range.0.end() as i64)
+            .collect::<Vec<&Command>>();
+        if slab_commands.is_empty() {
+            continue;
+        }
+
+        let y_bounds = range_boundaries(slab_commands.iter().map(|command| &command.region.y_range));
+        let z_bounds = range_boundaries(slab_commands.iter().map(|command| &command.region.z_range));
+        // Cells run from each boundary up to the next, so a range covers the cells from its start up to
+        // the boundary after its end
+        let cell_range = |bounds: &[i64], range: &CoordRange| {
+            let idx = |coord: i64| bounds.binary_search(&coord).expect("Command coordinate missing from boundaries");
+            idx(*range.0.start() as i64)..idx(*range.0.end() as i64 + 1)
+        };
+
+        // Later commands overwrite earlier ones within the slab
+        let mut cells = vec![vec![false; z_bounds.len() - 1]; y_bounds.len() - 1];
+        for command in slab_commands {
+            let assign_value = command.instruction == Instruction::On;
+            for row in &mut cells[cell_range(&y_bounds, &command.region.y_range)] {
+                row[cell_range(&z_bounds, &command.region.z_range)].fill(assign_value);
+            }
+        }
+
+        let slab_area: u128 = iproduct!(0..cells.len(), 0..cells[0].len())
+            .filter(|&(y_idx, z_idx)| cells[y_idx][z_idx])
+            .map(|(y_idx, z_idx)| ((y_bounds[y_idx+1] - y_bounds[y_idx]) * (z_bounds[z_idx+1] - z_bounds[z_idx])) as u128)
+            .sum();
+        volume += slab_area * (slab[1] - slab[0]) as u128;
+    }
+    volume
+}
+
+// Sorted unique coordinates where any of the ranges begin, or begin to no longer apply. Held as i64
+// so that a range ending at the largest i32 still has a boundary after it.
+fn range_boundaries<'a>(ranges: impl Iterator<Item = &'a CoordRange>) -> Vec<i64> {
+    ranges
+        .flat_map(|range| [*range.0.start() as i64, *range.0.end() as i64 + 1])
+        .sorted()
+        .dedup()
+        .collect()
+}
+
+fn splitting_lit_volume(commands: &[Command]) -> u128 {
+    rebooted_reactor(commands).lit_volume()
+}
+
+fn rebooted_reactor(commands: &[Command]) -> Reactor {
+    let mut reactor = Reactor::new();
+    commands.iter().for_each(|command| reactor.apply(command));
+    reactor
+}
+
+// Write the lit regions after the full reboot to any files requested on the command line
+fn export_lit_regions(commands: &[Command]) {
+    let obj_path = flag_value("--export-obj");
+    let slices_prefix = flag_value("--export-slices");
+    if obj_path.is_none() && slices_prefix.is_none() {
+        return;
+    }
+
+    let reactor = rebooted_reactor(commands);
+
+    if let Some(path) = obj_path {
+        match export::write_obj(reactor.lit_regions(), &path) {
+            Ok(()) => println!("Wrote {} lit cuboids to {}", reactor.lit_regions().len(), path),
+            Err(error) => println!("Unable to write {}: {}", path, error)
+        }
+    }
+
+    if let Some(prefix) = slices_prefix {
+        let axis_name = flag_value("--axis").unwrap_or_else(|| String::from("z"));
+        let axis = export::Axis::from_name(&axis_name).expect("Axis must be one of x, y or z");
+        let max_size = flag_value("--slice-size")
+            .map(|size| size.parse::<i64>().expect("Slice size must be a number"))
+            .unwrap_or(256);
+        // Each pixel has to cover at least one cube
+        if max_size < 1 {
+            println!("Slice size must be at least 1, but was {}", max_size);
+            return;
+        }
+        match export::write_slices(reactor.lit_regions(), axis, &prefix, max_size) {
+            Ok(count) => println!("Wrote {} slices along {} to {}*.ppm", count, axis_name, prefix),
+            Err(error) => println!("Unable to write slices to {}: {}", prefix, error)
+        }
+    }
+}
+
+// Alternative to splitting cubes apart, using inclusion-exclusion over cuboids that are allowed to
+// overlap. Each cuboid counts towards the volume either positively or negatively, and whenever a
+// command overlaps a cuboid their intersection is added with the opposite sign to cancel it out.
+fn signed_lit_volume(commands: &[Command]) -> u128 {
+    let mut signed_cubes = Vec::<(Cube, i8)>::new();
 
     commands.iter().for_each(|command| {
-        let assign_value = command.instruction == Instruction::On;
-        iproduct!(
-            clip_to_region(command.region.x_range.clone()),
-            clip_to_region(command.region.y_range.clone()),
-            clip_to_region(command.region.z_range.clone())
-        ).for_each(|(x, y, z)| {
-            region[add_offset(x)][add_offset(y)][add_offset(z)] = assign_value;
-        });
+        let mut cancellations = signed_cubes.iter()
+            .filter(|(cube, _)| cube.intersects(&command.region))
+            .map(|(cube, sign)| (cube.intersection(&command.region), -sign))
+            .collect::<Vec<(Cube, i8)>>();
+        signed_cubes.append(&mut cancellations);
+
+        // With any overlap cancelled, an ON command region can then be counted in full
+        if command.instruction == Instruction::On {
+            signed_cubes.push((command.region.clone(), 1));
+        }
     });
 
-    region.iter()
-        .flat_map(|x| x.iter())
-        .flat_map(|x| x.iter())
-        .filter(|&square| *square)
-        .count()
+    let volume: i128 = signed_cubes.iter()
+        .map(|(cube, sign)| cube.size() as i128 * *sign as i128)
+        .sum();
+    volume as u128
 }
 
-fn solution22b(commands: &[Command]) -> u128 {    
-    let mut active_regions = Vec::<Cube>::new();
+// Print the reactor state after each reboot step, and optionally whether a queried cube ends up on
+fn inspect_reboot(commands: &[Command]) {
+    let initialisation_region = initialisation_region();
 
-    commands.iter().for_each(|command| {
-        let mut new_cubes: Vec<Cube> = active_regions
+    let mut reactor = Reactor::new();
+    for (step, command) in commands.iter().enumerate() {
+        reactor.apply(command);
+        println!(
+            "Step {} ({}): {} lit in {} cuboids, {} within initialisation region",
+            step + 1, command,
+            reactor.lit_volume(), reactor.lit_regions().len(),
+            reactor.lit_volume_within(&initialisation_region)
+        );
+    }
+
+    if let Some(query) = flag_value("--query") {
+        let coords = query.split(',')
+            .map(|coord| coord.parse::<i32>().expect("Query coordinates must be integers"))
+            .collect::<Vec<i32>>();
+        assert!(coords.len() == 3, "Query must be given as x,y,z");
+        println!("{} is {}", query, if reactor.is_on(coords[0], coords[1], coords[2]) {"on"} else {"off"});
+    }
+}
+
+// The state of the reactor as a list of disjoint cuboids that are on, built up by applying
+// reboot steps one at a time
+struct Reactor {
+    lit_regions: Vec<Cube>
+}
+
+impl Reactor {
+    fn new() -> Self {
+        Self { lit_regions: Vec::<Cube>::new() }
+    }
+
+    fn apply(&mut self, command: &Command) {
+        let mut new_cubes: Vec<Cube> = self.lit_regions
             // Remove any intersected regions for re-processing
             .drain_filter(|region| region.intersects(&command.region))
             // Subtract the current command region from any intersected regions
@@ -58,12 +239,31 @@ fn solution22b(commands: &[Command]) -> u128 {
             .collect();
 
         // The filtered regions vector has new and re-processed cubes added to the end
-        active_regions.append(&mut new_cubes);
-    });
+        self.lit_regions.append(&mut new_cubes);
+    }
+
+    fn is_on(&self, x: i32, y: i32, z: i32) -> bool {
+        // Lit regions are disjoint, so at most one of them can hold the cube
+        self.lit_regions.iter()
+            .any(|region| region.contains_point(x, y, z))
+    }
+
+    fn lit_volume(&self) -> u128 {
+        self.lit_regions.iter()
+            .map(|region| region.size())
+            .sum()
+    }
+
+    fn lit_volume_within(&self, bounds: &Cube) -> u128 {
+        self.lit_regions.iter()
+            .filter(|region| region.intersects(bounds))
+            .map(|region| region.intersection(bounds).size())
+            .sum()
+    }
 
-    active_regions.iter()
-        .map(|region| region.size())
-        .sum()
+    fn lit_regions(&self) -> &[Cube] {
+        &self.lit_regions
+    }
 }
 
 fn parse_commands(input_lines: &[String]) -> Vec<Command> {
@@ -93,104 +293,149 @@ fn parse_commands(input_lines: &[String]) -> Vec<Command> {
         .collect()
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Command {
     instruction: Instruction,
     region: Cube
 }
 
-#[derive(Debug, Clone)]
-struct Cube{
-    x_range: CoordRange,
-    y_range: CoordRange,
-    z_range: CoordRange,
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", if self.instruction == Instruction::On {"on"} else {"off"}, self.region)
+    }
 }
 
-impl Cube {
-    fn is_empty(&self) -> bool {
-        self.x_range.is_empty() ||
-        self.y_range.is_empty() ||
-        self.z_range.is_empty()
-    }
+#[derive(Debug, Clone, PartialEq)]
+enum Instruction {On, Off}
 
-    fn size(&self) -> u128 {
-        vec!(&self.x_range, &self.y_range, &self.z_range).iter()
-            .map(|range| (range.0.end() - range.0.start() + 1) as u128)
-            .product()
-    }
+// The signed solver is an alternative for inputs that fragment badly when split, so compare how
+// long each solver takes over random reboot sequences and the puzzle input
+fn time_solvers(commands: &[Command], rng: &mut Rng, trials: usize) {
+    let mut splitting_time = Duration::ZERO;
+    let mut signed_time = Duration::ZERO;
 
-    fn contains(&self, other: &Self) -> bool {
-        self.x_range.contains(&other.x_range) &&
-        self.y_range.contains(&other.y_range) &&
-        self.z_range.contains(&other.z_range)
-    }
+    let random_sequences = (0..trials)
+        .map(|_| random_commands(rng, 30, 1000));
+    for sequence in random_sequences.chain([commands.to_vec()]) {
+        let start = Instant::now();
+        splitting_lit_volume(&sequence);
+        splitting_time += start.elapsed();
 
-    fn intersection(&self, other: &Self) -> Self {
-        Self {
-            x_range: self.x_range.intersect_ranges(&other.x_range),
-            y_range: self.y_range.intersect_ranges(&other.y_range),
-            z_range: self.z_range.intersect_ranges(&other.z_range)
-        }
+        let start = Instant::now();
+        signed_lit_volume(&sequence);
+        signed_time += start.elapsed();
     }
 
-    fn intersects(&self, other: &Self) -> bool {
-        let intersection = self.intersection(other);
-        !intersection.x_range.is_empty() &&
-        !intersection.y_range.is_empty() &&
-        !intersection.z_range.is_empty()
-    }
+    println!(
+        "Solved {} sequences in {:.1?} with the splitting solver and {:.1?} with the signed solver",
+        trials + 1, splitting_time, signed_time
+    );
+}
 
-    fn subtract_intersection(&self, intersection: &Self) -> Vec<Self> {
-        let mut subcubes = Vec::<Self>::new();
+// Random reboot steps with corners no further than the given extent from the origin
+fn random_commands(rng: &mut Rng, count: usize, extent: i32) -> Vec<Command> {
+    (0..count)
+        .map(|_| Command {
+            instruction: if rng.below(2) == 0 {Instruction::On} else {Instruction::Off},
+            region: Cube {
+                x_range: random_range(rng, extent),
+                y_range: random_range(rng, extent),
+                z_range: random_range(rng, extent)
+            }
+        })
+        .collect()
+}
 
-        iproduct!(
-            self.x_range.range_segments(&intersection.x_range),
-            self.y_range.range_segments(&intersection.y_range),
-            self.z_range.range_segments(&intersection.z_range)
-        )
-            .filter(|(x_range, y_range, z_range)| {
-                !x_range.equal_ranges(&intersection.x_range) ||
-                !y_range.equal_ranges(&intersection.y_range) ||
-                !z_range.equal_ranges(&intersection.z_range)
-            })
-            .for_each(|(x_range, y_range, z_range)| {
-                subcubes.push(Cube { x_range, y_range, z_range });
-            });
-        subcubes
-    }
+fn random_range(rng: &mut Rng, extent: i32) -> CoordRange {
+    let start = rng.between(-extent, extent);
+    CoordRange(start..=rng.between(start, extent))
 }
 
-#[derive(Debug, PartialEq)]
-enum Instruction {On, Off}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-#[derive(Clone, Debug)]
-struct CoordRange(RangeInclusive<i32>);
+    // Brute force over every cube within the bounds, only usable when the bounds are small
+    fn voxel_lit_volume(commands: &[Command], bounds: &Cube) -> u128 {
+        let size = |range: &CoordRange| (range.0.end() - range.0.start() + 1) as usize;
+        let offset = |coord: i32, range: &CoordRange| (coord - range.0.start()) as usize;
+        let (x_size, y_size, z_size) = (size(&bounds.x_range), size(&bounds.y_range), size(&bounds.z_range));
+        let mut region = vec![false; x_size * y_size * z_size];
 
-impl CoordRange {
-    fn is_empty(&self) -> bool { self.0.is_empty() }
+        commands.iter().for_each(|command| {
+            let assign_value = command.instruction == Instruction::On;
+            let clipped = command.region.intersection(bounds);
+            iproduct!(
+                clipped.x_range.0.clone(),
+                clipped.y_range.0.clone(),
+                clipped.z_range.0.clone()
+            ).for_each(|(x, y, z)| {
+                region[
+                    (offset(x, &bounds.x_range) * y_size + offset(y, &bounds.y_range)) * z_size
+                    + offset(z, &bounds.z_range)
+                ] = assign_value;
+            });
+        });
 
-    fn intersect_ranges(&self, other: &Self) -> Self {
-        Self (
-            max(*self.0.start(), *other.0.start())..=min(*self.0.end(),*other.0.end())
-        )
+        region.iter()
+            .filter(|&square| *square)
+            .count() as u128
     }
 
-    fn equal_ranges(&self, other: &Self) -> bool {
-        *self.0.start() == *other.0.start()  && *self.0.end() == *other.0.end()
-    }
+    // The splitting and signed solvers must agree on random reboot sequences and on the puzzle input
+    #[test]
+    fn splitting_and_signed_solvers_agree() {
+        let mut rng = Rng::new(22);
+        let random_sequences = (0..200)
+            .map(|_| random_commands(&mut rng, 30, 1000))
+            .collect::<Vec<Vec<Command>>>();
+        let puzzle_input = parse_commands(&read_string_lines("src/data/solution22.txt"));
 
-    fn range_segments(&self, intersection: &Self) -> Vec<Self> {
-        vec!(
-            *self.0.start()..=(*intersection.0.start()-1),
-            intersection.0.clone(),
-            (*intersection.0.end()+1)..=*self.0.end()
-        ).into_iter()
-            .filter(|range| *range.end() >= *range.start())
-            .map(CoordRange)
-            .collect()
+        for sequence in random_sequences.iter().chain([&puzzle_input]) {
+            assert_eq!(splitting_lit_volume(sequence), signed_lit_volume(sequence), "Solvers disagree on {:?}", sequence);
+        }
     }
 
-    fn contains(&self, other: &Self) -> bool {
-        self.0.start() <= other.0.end() && self.0.end() < other.0.start()
+    // The compression and splitting solvers must agree with brute force on random small reboot
+    // sequences, both over all space and clipped to random bounds
+    #[test]
+    fn solvers_agree_with_voxels() {
+        let mut rng = Rng::new(22);
+        let extent = 12;
+        let everywhere = Cube {
+            x_range: CoordRange(-extent..=extent),
+            y_range: CoordRange(-extent..=extent),
+            z_range: CoordRange(-extent..=extent)
+        };
+        for _ in 0..500 {
+            let count = 1 + rng.below(12) as usize;
+            let commands = random_commands(&mut rng, count, extent);
+            let bounds = Cube {
+                x_range: random_range(&mut rng, extent),
+                y_range: random_range(&mut rng, extent),
+                z_range: random_range(&mut rng, extent)
+            };
+
+            let mut reactor = Reactor::new();
+            commands.iter().for_each(|command| reactor.apply(command));
+
+            let volumes = [
+                voxel_lit_volume(&commands, &everywhere),
+                compressed_lit_volume(&commands, None),
+                reactor.lit_volume()
+            ];
+            let bounded_volumes = [
+                voxel_lit_volume(&commands, &bounds),
+                compressed_lit_volume(&commands, Some(&bounds)),
+                reactor.lit_volume_within(&bounds)
+            ];
+
+            let disagree = |volumes: &[u128]| volumes.iter().any(|&volume| volume != volumes[0]);
+            assert!(
+                !disagree(&volumes) && !disagree(&bounded_volumes),
+                "Solvers disagree (voxel, compressed, splitting): {:?} overall, {:?} within {}",
+                volumes, bounded_volumes, bounds
+            );
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,117 @@
+use super::geometry::{CoordRange, Cube};
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+// Write each cuboid as a box in Wavefront OBJ format. Cuboids cover whole cubes, so each box runs
+// from the start of its ranges to one past their end.
+pub fn write_obj(cubes: &[Cube], path: &str) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "# {} lit cuboids", cubes.len())?;
+
+    for (cube_idx, cube) in cubes.iter().enumerate() {
+        let bounds = |range: &CoordRange| [*range.0.start() as i64, *range.0.end() as i64 + 1];
+        for x in bounds(&cube.x_range) {
+            for y in bounds(&cube.y_range) {
+                for z in bounds(&cube.z_range) {
+                    writeln!(writer, "v {} {} {}", x, y, z)?;
+                }
+            }
+        }
+
+        // Vertex indices are 1-based and run through z fastest, then y, then x
+        let base = cube_idx * 8 + 1;
+        for face in [[0, 1, 3, 2], [4, 6, 7, 5], [0, 4, 5, 1], [2, 3, 7, 6], [0, 2, 6, 4], [1, 5, 7, 3]] {
+            writeln!(writer, "f {} {} {} {}", base + face[0], base + face[1], base + face[2], base + face[3])?;
+        }
+    }
+    writer.flush()
+}
+
+#[derive(Clone, Copy)]
+pub enum Axis {X, Y, Z}
+
+impl Axis {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "x" => Some(Self::X),
+            "y" => Some(Self::Y),
+            "z" => Some(Self::Z),
+            _ => None
+        }
+    }
+
+    // The range of the cube along this axis, followed by the ranges across the image
+    fn split<'a>(&self, cube: &'a Cube) -> (&'a CoordRange, &'a CoordRange, &'a CoordRange) {
+        match self {
+            Self::X => (&cube.x_range, &cube.y_range, &cube.z_range),
+            Self::Y => (&cube.y_range, &cube.x_range, &cube.z_range),
+            Self::Z => (&cube.z_range, &cube.x_range, &cube.y_range)
+        }
+    }
+}
+
+// Write a stack of binary PPM images slicing through the cuboids along the axis, numbered from the
+// lowest slice upwards. Reboot regions span far more cubes than an image can hold, so every pixel and
+// slice stands for a block of cubes, scaled so no image side or slice count exceeds the maximum size.
+// A pixel is lit if any cube of its block on the slice plane is lit. Returns the number of images.
+pub fn write_slices(cubes: &[Cube], axis: Axis, path_prefix: &str, max_size: i64) -> io::Result<usize> {
+    if cubes.is_empty() {
+        return Ok(0);
+    }
+
+    // Bounding box of all the lit cuboids, along the axis and then across the image
+    let split_cubes = cubes.iter()
+        .map(|cube| axis.split(cube))
+        .collect::<Vec<(&CoordRange, &CoordRange, &CoordRange)>>();
+    let extents = [
+        bounding_range(split_cubes.iter().map(|ranges| ranges.0)),
+        bounding_range(split_cubes.iter().map(|ranges| ranges.1)),
+        bounding_range(split_cubes.iter().map(|ranges| ranges.2))
+    ];
+
+    let scale = extents.iter()
+        .map(|(min, max)| (max - min + 1 + max_size - 1) / max_size)
+        .max()
+        .unwrap()
+        .max(1);
+    let scaled_size = |(min, max): (i64, i64)| ((max - min) / scale + 1) as usize;
+    let (slice_count, width, height) = (scaled_size(extents[0]), scaled_size(extents[1]), scaled_size(extents[2]));
+
+    for slice_idx in 0..slice_count {
+        let slice_coord = extents[0].0 + slice_idx as i64 * scale;
+        let mut pixels = vec![false; width * height];
+
+        for &(slice_range, column_range, row_range) in &split_cubes {
+            if !slice_range.0.contains(&(slice_coord as i32)) {
+                continue;
+            }
+
+            // Pixel positions covered by the range, from its first block to its last
+            let scaled = |range: &CoordRange, min: i64| {
+                ((*range.0.start() as i64 - min) / scale) as usize..=((*range.0.end() as i64 - min) / scale) as usize
+            };
+            for row in scaled(row_range, extents[2].0) {
+                for column in scaled(column_range, extents[1].0) {
+                    pixels[row * width + column] = true;
+                }
+            }
+        }
+
+        let mut writer = BufWriter::new(File::create(format!("{}{:04}.ppm", path_prefix, slice_idx))?);
+        write!(writer, "P6\n{} {}\n255\n", width, height)?;
+        for lit in pixels {
+            writer.write_all(if lit {&[255, 200, 50]} else {&[0, 0, 0]})?;
+        }
+        writer.flush()?;
+    }
+    Ok(slice_count)
+}
+
+// Lowest start and highest end over all the ranges
+fn bounding_range<'a>(ranges: impl Iterator<Item = &'a CoordRange>) -> (i64, i64) {
+    ranges.fold((i64::MAX, i64::MIN), |(min, max), range| (
+        min.min(*range.0.start() as i64),
+        max.max(*range.0.end() as i64)
+    ))
+}
@@ -0,0 +1,226 @@
+use itertools::iproduct;
+
+use std::{fmt, ops::RangeInclusive, cmp::{max, min}};
+
+#[derive(Debug, Clone)]
+pub struct Cube{
+    pub x_range: CoordRange,
+    pub y_range: CoordRange,
+    pub z_range: CoordRange,
+}
+
+impl Cube {
+    pub fn is_empty(&self) -> bool {
+        self.x_range.is_empty() ||
+        self.y_range.is_empty() ||
+        self.z_range.is_empty()
+    }
+
+    pub fn size(&self) -> u128 {
+        if self.is_empty() {
+            return 0;
+        }
+        vec!(&self.x_range, &self.y_range, &self.z_range).iter()
+            .map(|range| (range.0.end() - range.0.start() + 1) as u128)
+            .product()
+    }
+
+    pub fn contains_point(&self, x: i32, y: i32, z: i32) -> bool {
+        self.x_range.0.contains(&x) &&
+        self.y_range.0.contains(&y) &&
+        self.z_range.0.contains(&z)
+    }
+
+    // Whether every cube of the other cuboid is also within this one
+    pub fn contains(&self, other: &Self) -> bool {
+        other.is_empty() || (
+            self.x_range.contains(&other.x_range) &&
+            self.y_range.contains(&other.y_range) &&
+            self.z_range.contains(&other.z_range)
+        )
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        Self {
+            x_range: self.x_range.intersect_ranges(&other.x_range),
+            y_range: self.y_range.intersect_ranges(&other.y_range),
+            z_range: self.z_range.intersect_ranges(&other.z_range)
+        }
+    }
+
+    pub fn intersects(&self, other: &Self) -> bool {
+        let intersection = self.intersection(other);
+        !intersection.x_range.is_empty() &&
+        !intersection.y_range.is_empty() &&
+        !intersection.z_range.is_empty()
+    }
+
+    pub fn subtract_intersection(&self, intersection: &Self) -> Vec<Self> {
+        let mut subcubes = Vec::<Self>::new();
+
+        iproduct!(
+            self.x_range.range_segments(&intersection.x_range),
+            self.y_range.range_segments(&intersection.y_range),
+            self.z_range.range_segments(&intersection.z_range)
+        )
+            .filter(|(x_range, y_range, z_range)| {
+                !x_range.equal_ranges(&intersection.x_range) ||
+                !y_range.equal_ranges(&intersection.y_range) ||
+                !z_range.equal_ranges(&intersection.z_range)
+            })
+            .for_each(|(x_range, y_range, z_range)| {
+                subcubes.push(Cube { x_range, y_range, z_range });
+            });
+        subcubes
+    }
+}
+
+// Written in the same notation as the puzzle input
+impl fmt::Display for Cube {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f, "x={}..{},y={}..{},z={}..{}",
+            self.x_range.0.start(), self.x_range.0.end(),
+            self.y_range.0.start(), self.y_range.0.end(),
+            self.z_range.0.start(), self.z_range.0.end()
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CoordRange(pub RangeInclusive<i32>);
+
+impl CoordRange {
+    fn is_empty(&self) -> bool { self.0.is_empty() }
+
+    fn intersect_ranges(&self, other: &Self) -> Self {
+        Self (
+            max(*self.0.start(), *other.0.start())..=min(*self.0.end(),*other.0.end())
+        )
+    }
+
+    fn equal_ranges(&self, other: &Self) -> bool {
+        *self.0.start() == *other.0.start()  && *self.0.end() == *other.0.end()
+    }
+
+    fn range_segments(&self, intersection: &Self) -> Vec<Self> {
+        vec!(
+            *self.0.start()..=(*intersection.0.start()-1),
+            intersection.0.clone(),
+            (*intersection.0.end()+1)..=*self.0.end()
+        ).into_iter()
+            .filter(|range| *range.end() >= *range.start())
+            .map(CoordRange)
+            .collect()
+    }
+
+    // Whether every coordinate of the other range is also within this one
+    fn contains(&self, other: &Self) -> bool {
+        other.is_empty() ||
+        (self.0.start() <= other.0.start() && other.0.end() <= self.0.end())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::Rng;
+    use std::collections::HashSet;
+
+    // The range operations must match the coordinates the ranges actually hold, checked exhaustively
+    // over every pair of small ranges including empty ones
+    #[test]
+    fn range_operations_match_coordinates() {
+        let extent = 4;
+        let ranges = iproduct!(-extent..=extent, -extent..=extent)
+            .map(|(start, end)| CoordRange(start..=end))
+            .collect::<Vec<CoordRange>>();
+        let coords = |range: &CoordRange| range.0.clone().collect::<HashSet<i32>>();
+
+        for (first, second) in iproduct!(&ranges, &ranges) {
+            let (first_coords, second_coords) = (coords(first), coords(second));
+            let intersection = first.intersect_ranges(second);
+
+            let mut failed = Vec::<&str>::new();
+            if coords(&intersection) != &first_coords & &second_coords {
+                failed.push("intersect_ranges");
+            }
+            if first.contains(second) != second_coords.is_subset(&first_coords) {
+                failed.push("contains");
+            }
+            if !first.is_empty() && !second.is_empty() && first.equal_ranges(second) != (first_coords == second_coords) {
+                failed.push("equal_ranges");
+            }
+
+            // Segments only apply when the intersection has something to split around
+            if !intersection.is_empty() {
+                let segments = first.range_segments(&intersection);
+                let segment_coords = segments.iter().map(coords).collect::<Vec<HashSet<i32>>>();
+                let covered = segment_coords.iter().flatten().copied().collect::<HashSet<i32>>();
+                let total: usize = segment_coords.iter().map(HashSet::len).sum();
+                if segments.iter().any(CoordRange::is_empty)
+                    || covered != first_coords
+                    || total != first_coords.len() // Overlapping segments would count some coordinates twice
+                    || !segments.iter().any(|segment| segment.equal_ranges(&intersection)) {
+                    failed.push("range_segments");
+                }
+            }
+
+            assert!(failed.is_empty(), "Range check failed for {:?} and {:?}: {}", first.0, second.0, failed.join(", "));
+        }
+    }
+
+    // The cuboid operations must match the points over random pairs of small cuboids. Most importantly
+    // the pieces left by subtracting an intersection must be disjoint, and together with the
+    // intersection must make up exactly the original cuboid.
+    #[test]
+    fn cuboid_operations_match_points() {
+        let mut rng = Rng::new(22);
+        let extent = 5;
+        let mut random_range = || {
+            // Occasionally produce empty ranges as well
+            let start = rng.between(-extent, extent);
+            CoordRange(start..=rng.between(start - 1, extent))
+        };
+        let points = |cube: &Cube| iproduct!(cube.x_range.0.clone(), cube.y_range.0.clone(), cube.z_range.0.clone())
+            .collect::<HashSet<(i32, i32, i32)>>();
+
+        for _ in 0..20000 {
+            let first = Cube {x_range: random_range(), y_range: random_range(), z_range: random_range()};
+            let second = Cube {x_range: random_range(), y_range: random_range(), z_range: random_range()};
+            let (first_points, second_points) = (points(&first), points(&second));
+            let intersection = first.intersection(&second);
+
+            let mut failed = Vec::<&str>::new();
+            if first.size() as usize != first_points.len() {
+                failed.push("size");
+            }
+            if first.is_empty() != first_points.is_empty() {
+                failed.push("is_empty");
+            }
+            if first.contains(&second) != second_points.is_subset(&first_points) {
+                failed.push("contains");
+            }
+            // Sharing no points means that the cuboids must not intersect
+            if first.intersects(&second) == first_points.is_disjoint(&second_points) {
+                failed.push("intersects");
+            }
+            if first_points.iter().any(|&(x, y, z)| !first.contains_point(x, y, z)) {
+                failed.push("contains_point");
+            }
+
+            if first.intersects(&second) {
+                let pieces = first.subtract_intersection(&intersection);
+                let pieces_volume: u128 = pieces.iter().map(Cube::size).sum();
+                let piece_points = pieces.iter().flat_map(points).collect::<HashSet<(i32, i32, i32)>>();
+                if pieces_volume + intersection.size() != first.size()
+                    || pieces_volume as usize != piece_points.len() // Overlapping pieces would count some points twice
+                    || pieces.iter().any(|piece| piece.is_empty() || piece.intersects(&intersection) || !first.contains(piece)) {
+                    failed.push("subtract_intersection");
+                }
+            }
+
+            assert!(failed.is_empty(), "Cuboid check failed for {} and {}: {}", first, second, failed.join(", "));
+        }
+    }
+}
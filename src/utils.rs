@@ -1,6 +1,7 @@
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::error::Error;
+use std::env;
 
 pub fn read_string_lines(path: &str) -> Vec<String> {
     iterate_file_lines(path)
@@ -98,4 +99,16 @@ fn box_error<E>(e: E) -> Box<dyn Error>
 where E: Error + 'static
 {
     Box::new(e)
-}
\ No newline at end of file
+}
+
+// Check whether a flag such as `--exact` was passed on the command line
+pub fn has_flag(flag: &str) -> bool {
+    env::args().any(|arg| arg == flag)
+}
+
+// Find the value given after a flag on the command line, such as `--steps 100`
+pub fn flag_value(flag: &str) -> Option<String> {
+    env::args()
+        .skip_while(|arg| arg != flag)
+        .nth(1)
+}